@@ -0,0 +1,372 @@
+//! Transports the client can run on: an RPC node, or the bank of a
+//! `solana-program-test` context with the `program-test` feature.
+
+use eyre::Error;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcProgramAccountsConfig, RpcSimulateTransactionConfig};
+use solana_client::rpc_filter::RpcFilterType;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::Account;
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_transaction_status::{UiTransactionEncoding, UiTransactionReturnData};
+use std::future::Future;
+use std::str::FromStr as _;
+
+/// Maximum number of accounts fetched by one `getMultipleAccounts` request.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Outcome of an executed transaction.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionLogs {
+    pub slot: u64,
+    pub log_messages: Vec<String>,
+    /// Program that set the return data, and the data.
+    pub return_data: Option<(Pubkey, Vec<u8>)>,
+}
+
+/// Outcome of a simulated transaction, nothing of which is committed.
+#[derive(Clone, Debug)]
+pub struct TransactionSimulation {
+    pub result: Result<(), TransactionError>,
+    pub logs: TransactionLogs,
+    pub units_consumed: u64,
+}
+
+pub trait MTreeBackend {
+    fn get_account(
+        &self,
+        key: &Pubkey,
+    ) -> impl Future<Output = Result<Option<Account>, Error>> + Send;
+
+    fn get_multiple_accounts(
+        &self,
+        keys: &[Pubkey],
+    ) -> impl Future<Output = Result<Vec<Option<Account>>, Error>> + Send;
+
+    /// Returns the accounts of `program_id` holding `data_len` bytes.
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        data_len: usize,
+    ) -> impl Future<Output = Result<Vec<(Pubkey, Account)>, Error>> + Send;
+
+    fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+
+    fn get_latest_blockhash(&self) -> impl Future<Output = Result<Hash, Error>> + Send;
+
+    /// Sends the transaction and waits for its confirmation.
+    fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> impl Future<Output = Result<Signature, Error>> + Send;
+
+    /// Simulates the transaction against the latest bank, with the
+    /// signatures and blockhash unchecked.
+    fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> impl Future<Output = Result<TransactionSimulation, Error>> + Send;
+
+    fn get_transaction_logs(
+        &self,
+        signature: &Signature,
+    ) -> impl Future<Output = Result<TransactionLogs, Error>> + Send;
+
+    /// Returns the signatures of the successful transactions using
+    /// `address`, from the newest.
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+    ) -> impl Future<Output = Result<Vec<Signature>, Error>> + Send;
+}
+
+impl MTreeBackend for RpcClient {
+    async fn get_account(&self, key: &Pubkey) -> Result<Option<Account>, Error> {
+        Ok(self
+            .get_account_with_commitment(key, self.commitment())
+            .await?
+            .value)
+    }
+
+    async fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>, Error> {
+        let mut accounts = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            accounts.extend(RpcClient::get_multiple_accounts(self, chunk).await?);
+        }
+        Ok(accounts)
+    }
+
+    async fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        data_len: usize,
+    ) -> Result<Vec<(Pubkey, Account)>, Error> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::DataSize(data_len as u64)]),
+            ..Default::default()
+        };
+        Ok(self
+            .get_program_accounts_with_config(program_id, config)
+            .await?)
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> Result<u64, Error> {
+        Ok(RpcClient::get_minimum_balance_for_rent_exemption(self, data_len).await?)
+    }
+
+    async fn get_latest_blockhash(&self) -> Result<Hash, Error> {
+        Ok(RpcClient::get_latest_blockhash(self).await?)
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature, Error> {
+        Ok(self.send_and_confirm_transaction(transaction).await?)
+    }
+
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionSimulation, Error> {
+        let config = RpcSimulateTransactionConfig {
+            replace_recent_blockhash: true,
+            commitment: Some(self.commitment()),
+            ..Default::default()
+        };
+        let response = self
+            .simulate_transaction_with_config(transaction, config)
+            .await?;
+        let simulation = response.value;
+
+        Ok(TransactionSimulation {
+            result: simulation.err.map_or(Ok(()), Err),
+            logs: TransactionLogs {
+                slot: response.context.slot,
+                log_messages: simulation.logs.unwrap_or_default(),
+                return_data: simulation.return_data.map(decode_return_data).transpose()?,
+            },
+            units_consumed: simulation.units_consumed.unwrap_or_default(),
+        })
+    }
+
+    async fn get_transaction_logs(&self, signature: &Signature) -> Result<TransactionLogs, Error> {
+        let confirmed = self
+            .get_transaction(signature, UiTransactionEncoding::Json)
+            .await?;
+        let meta = confirmed
+            .transaction
+            .meta
+            .ok_or_else(|| eyre::eyre!("No transaction meta"))?;
+        let log_messages = meta
+            .log_messages
+            .ok_or_else(|| eyre::eyre!("No log messages"))?;
+        let return_data: Option<UiTransactionReturnData> = meta.return_data.into();
+
+        Ok(TransactionLogs {
+            slot: confirmed.slot,
+            log_messages,
+            return_data: return_data.map(decode_return_data).transpose()?,
+        })
+    }
+
+    async fn get_signatures_for_address(&self, address: &Pubkey) -> Result<Vec<Signature>, Error> {
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                ..Default::default()
+            };
+            let page = self
+                .get_signatures_for_address_with_config(address, config)
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            before = Some(Signature::from_str(&last.signature)?);
+            for status in page.into_iter().filter(|status| status.err.is_none()) {
+                signatures.push(Signature::from_str(&status.signature)?);
+            }
+        }
+        Ok(signatures)
+    }
+}
+
+fn decode_return_data(return_data: UiTransactionReturnData) -> Result<(Pubkey, Vec<u8>), Error> {
+    use base64::prelude::{Engine as _, BASE64_STANDARD};
+    Ok((
+        Pubkey::from_str(&return_data.program_id)?,
+        BASE64_STANDARD.decode(&return_data.data.0)?,
+    ))
+}
+
+/// Returns the error of a transaction rejected by the chain, as raised by
+/// the `send_transaction` of any backend.
+pub fn transaction_error(err: &Error) -> Option<TransactionError> {
+    if let Some(err) = err.downcast_ref::<solana_client::client_error::ClientError>() {
+        return err.get_transaction_error();
+    }
+    err.downcast_ref::<TransactionError>().cloned()
+}
+
+#[cfg(feature = "program-test")]
+pub use banks::BanksBackend;
+
+#[cfg(feature = "program-test")]
+mod banks {
+    use super::{MTreeBackend, TransactionLogs, TransactionSimulation};
+    use eyre::Error;
+    use solana_program::pubkey::Pubkey;
+    use solana_program_test::BanksClient;
+    use solana_sdk::account::Account;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::signature::Signature;
+    use solana_sdk::transaction::Transaction;
+    use std::sync::Mutex;
+
+    struct ExecutedTransaction {
+        signature: Signature,
+        account_keys: Vec<Pubkey>,
+        logs: TransactionLogs,
+    }
+
+    /// Backend on the bank of a `solana-program-test` context. The bank
+    /// keeps no transaction history, so logs and signatures are only known
+    /// for the transactions sent through this backend, and program accounts
+    /// cannot be listed.
+    pub struct BanksBackend {
+        client: BanksClient,
+        executed: Mutex<Vec<ExecutedTransaction>>,
+    }
+
+    impl BanksBackend {
+        pub fn new(client: BanksClient) -> Self {
+            Self {
+                client,
+                executed: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl MTreeBackend for BanksBackend {
+        async fn get_account(&self, key: &Pubkey) -> Result<Option<Account>, Error> {
+            Ok(self.client.clone().get_account(*key).await?)
+        }
+
+        async fn get_multiple_accounts(
+            &self,
+            keys: &[Pubkey],
+        ) -> Result<Vec<Option<Account>>, Error> {
+            let mut accounts = Vec::with_capacity(keys.len());
+            for key in keys {
+                accounts.push(self.get_account(key).await?);
+            }
+            Ok(accounts)
+        }
+
+        async fn get_program_accounts(
+            &self,
+            _program_id: &Pubkey,
+            _data_len: usize,
+        ) -> Result<Vec<(Pubkey, Account)>, Error> {
+            Err(eyre::eyre!("BanksClient cannot list program accounts"))
+        }
+
+        async fn get_minimum_balance_for_rent_exemption(
+            &self,
+            data_len: usize,
+        ) -> Result<u64, Error> {
+            let rent = self.client.clone().get_rent().await?;
+            Ok(rent.minimum_balance(data_len))
+        }
+
+        async fn get_latest_blockhash(&self) -> Result<Hash, Error> {
+            Ok(self.client.clone().get_latest_blockhash().await?)
+        }
+
+        async fn send_transaction(&self, transaction: &Transaction) -> Result<Signature, Error> {
+            let client = self.client.clone();
+            let executed = client
+                .process_transaction_with_metadata(transaction.clone())
+                .await?;
+            executed.result?;
+            let slot = client.get_root_slot().await?;
+
+            let metadata = executed
+                .metadata
+                .ok_or_else(|| eyre::eyre!("No transaction metadata"))?;
+            let signature = transaction.signatures[0];
+            self.executed.lock().unwrap().push(ExecutedTransaction {
+                signature,
+                account_keys: transaction.message.account_keys.clone(),
+                logs: TransactionLogs {
+                    slot,
+                    log_messages: metadata.log_messages,
+                    return_data: metadata
+                        .return_data
+                        .map(|return_data| (return_data.program_id, return_data.data)),
+                },
+            });
+            Ok(signature)
+        }
+
+        async fn simulate_transaction(
+            &self,
+            transaction: &Transaction,
+        ) -> Result<TransactionSimulation, Error> {
+            let client = self.client.clone();
+            let simulated = client.simulate_transaction(transaction.clone()).await?;
+            let details = simulated
+                .simulation_details
+                .ok_or_else(|| eyre::eyre!("No simulation details"))?;
+
+            Ok(TransactionSimulation {
+                result: simulated
+                    .result
+                    .ok_or_else(|| eyre::eyre!("No simulation result"))?,
+                logs: TransactionLogs {
+                    slot: client.get_root_slot().await?,
+                    log_messages: details.logs,
+                    return_data: details
+                        .return_data
+                        .map(|return_data| (return_data.program_id, return_data.data)),
+                },
+                units_consumed: details.units_consumed,
+            })
+        }
+
+        async fn get_transaction_logs(
+            &self,
+            signature: &Signature,
+        ) -> Result<TransactionLogs, Error> {
+            self.executed
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|executed| executed.signature == *signature)
+                .map(|executed| executed.logs.clone())
+                .ok_or_else(|| {
+                    eyre::eyre!("Transaction {} was not sent by this backend", signature)
+                })
+        }
+
+        async fn get_signatures_for_address(
+            &self,
+            address: &Pubkey,
+        ) -> Result<Vec<Signature>, Error> {
+            Ok(self
+                .executed
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .filter(|executed| executed.account_keys.contains(address))
+                .map(|executed| executed.signature)
+                .collect())
+        }
+    }
+}
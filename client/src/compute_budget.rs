@@ -0,0 +1,53 @@
+//! Compute budget of the transactions sent by the client.
+
+use solana_program::instruction::Instruction;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+/// Highest compute unit limit of a transaction.
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Margin added to the simulated compute units, in percent, as the accounts
+/// may change before the transaction lands.
+pub const COMPUTE_UNIT_MARGIN: u32 = 10;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ComputeUnitLimit {
+    /// No limit instruction, the runtime default applies.
+    #[default]
+    Default,
+    Fixed(u32),
+    /// Simulates each transaction first and sets its limit to the units it
+    /// consumed plus `COMPUTE_UNIT_MARGIN`.
+    Estimate,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ComputeBudget {
+    pub unit_limit: ComputeUnitLimit,
+    /// Priority fee in micro-lamports per compute unit.
+    pub unit_price: Option<u64>,
+}
+
+impl ComputeBudget {
+    /// Returns the compute budget instructions to prepend to a transaction
+    /// with the given unit limit.
+    pub fn instructions(&self, unit_limit: Option<u32>) -> Vec<Instruction> {
+        let mut instructions = Vec::with_capacity(2);
+        if let Some(units) = unit_limit {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+        }
+        if let Some(micro_lamports) = self.unit_price {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+                micro_lamports,
+            ));
+        }
+        instructions
+    }
+}
+
+/// Returns the unit limit for a transaction which consumed `units` in
+/// simulation.
+pub fn estimated_unit_limit(units: u64) -> u32 {
+    let units = units + units * COMPUTE_UNIT_MARGIN as u64 / 100;
+    units.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
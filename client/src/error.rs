@@ -0,0 +1,98 @@
+//! Errors of the client, telling program errors of the mtree program
+//! apart from transport and state errors.
+
+use crate::backend::transaction_error;
+use solana_program::instruction::InstructionError;
+use solana_program::pubkey::Pubkey;
+use solana_program_mtree::error::MtreeError;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MTreeClientError {
+    /// An instruction of the mtree program failed.
+    #[error("Program error: {0}")]
+    Program(MtreeError),
+
+    /// The transaction was rejected for another reason.
+    #[error("Transaction failed: {0}")]
+    Transaction(TransactionError),
+
+    /// The backend failed to answer, e.g. the RPC node is unreachable.
+    #[error("Transport error: {0:#}")]
+    Transport(eyre::Error),
+
+    #[error("Tree is not initialized")]
+    UninitializedTree,
+
+    #[error("Account {0} not found")]
+    AccountNotFound(Pubkey),
+
+    #[error("Borsh error: {0}")]
+    Borsh(#[from] io::Error),
+
+    /// The transaction has no event or return data of the expected kind.
+    #[error("No {0} in the transaction")]
+    MissingEvent(&'static str),
+
+    #[error("Leaf {0} is not in the tree")]
+    LeafNotFound(u64),
+
+    /// Accounts or events read from the chain disagree, usually because the
+    /// tree changed in between.
+    #[error("Inconsistent tree state: {0}")]
+    Inconsistent(&'static str),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+impl MTreeClientError {
+    /// Classifies an error of `MTreeBackend::send_transaction`. The custom
+    /// error code of a failed instruction is decoded only if the instruction
+    /// belongs to `program_id`.
+    pub fn from_send_error(
+        err: eyre::Error,
+        transaction: &Transaction,
+        program_id: &Pubkey,
+    ) -> Self {
+        match transaction_error(&err) {
+            Some(tx_err) => Self::from_transaction_error(tx_err, transaction, program_id),
+            None => Self::Transport(err),
+        }
+    }
+
+    /// Classifies the error of `transaction`, see `from_send_error`.
+    pub fn from_transaction_error(
+        tx_err: TransactionError,
+        transaction: &Transaction,
+        program_id: &Pubkey,
+    ) -> Self {
+        if let TransactionError::InstructionError(index, InstructionError::Custom(code)) = tx_err {
+            let instruction_program_id = transaction
+                .message
+                .instructions
+                .get(index as usize)
+                .map(|instruction| instruction.program_id(&transaction.message.account_keys));
+            if instruction_program_id == Some(program_id) {
+                if let Ok(err) = MtreeError::try_from(code) {
+                    return Self::Program(err);
+                }
+            }
+        }
+        Self::Transaction(tx_err)
+    }
+
+    pub fn program_error(&self) -> Option<&MtreeError> {
+        match self {
+            Self::Program(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<eyre::Error> for MTreeClientError {
+    fn from(err: eyre::Error) -> Self {
+        Self::Transport(err)
+    }
+}
@@ -1,77 +1,262 @@
-use borsh::BorshDeserialize as _;
-use eyre::Error;
-use solana_client::rpc_client::RpcClient;
+//! Client of the mtree program. `nonblocking::MTreeClient` holds the logic
+//! for async code, `MTreeClient` runs it to completion for blocking code.
+
+use crate::compute_budget::ComputeBudget;
+use crate::MTreeClientError as Error;
 use solana_program::pubkey::Pubkey;
-use solana_program_mtree::events::MTreeEvent;
-use solana_program_mtree::info::{find_info_pda, MTreeInfo};
-use solana_program_mtree::instruction::encode::make_insert_leaf_instruction;
+use solana_program_mtree::info::{LeafMarker, MTreeInfo, SignedLeafMarker, TreeConfig};
+use solana_program_mtree::mtree::audit::AuditIssue;
+use solana_program_mtree::mtree::indexed::{IndexedLeafProof, IndexedTree, IndexedTreeLeaves};
+use solana_program_mtree::mtree::local::LocalTree;
+use solana_program_mtree::mtree::mmr::{Mmr, MmrConsistencyProof, MmrLeaves, MmrProof};
+use solana_program_mtree::mtree::proof::{MerkleMultiProof, MerkleProof};
+use solana_program_mtree::mtree::sparse::{SparseKey, SparseProof, SparseTree, SparseTreeEntries};
 use solana_program_mtree::mtree::Hash;
-use solana_sdk::signature::Signature;
-use solana_sdk::{signature::Keypair, signer::Signer, transaction::Transaction};
-use solana_transaction_status::UiTransactionEncoding;
+use solana_program_mtree::receipt::ProofReceipt;
+use solana_sdk::signature::{Keypair, Signature};
+use std::future::Future;
+
+pub mod backend;
+pub mod compute_budget;
+pub mod error;
+pub mod nonblocking;
+
+pub use error::MTreeClientError;
 
+/// Bytes of leaf data written by one `WriteBuffer` transaction, small enough
+/// to keep the transaction under the packet size limit.
+pub const BUFFER_CHUNK_SIZE: usize = 900;
+
+/// Blocking client of the mtree program, see `nonblocking::MTreeClient` for
+/// the documentation of each call.
 pub struct MTreeClient {
-    program_id: Pubkey,
-    client: RpcClient,
+    client: nonblocking::MTreeClient,
+    runtime: tokio::runtime::Runtime,
 }
 
 impl MTreeClient {
     pub fn new(program_id: Pubkey, url: &str) -> Self {
         Self {
-            program_id,
-            client: RpcClient::new(url),
+            client: nonblocking::MTreeClient::new(program_id, url),
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .enable_time()
+                .build()
+                .expect("runtime"),
         }
     }
 
-    fn get_info(&self) -> Result<MTreeInfo, Error> {
-        let (info_pda, _) = find_info_pda(&self.program_id);
-        let account = self.client.get_account(&info_pda)?;
-        let mtree_info = MTreeInfo::try_from_slice(&account.data)?;
-        Ok(mtree_info)
+    /// Sets the compute budget of the transactions sent by the client.
+    pub fn with_compute_budget(mut self, compute_budget: ComputeBudget) -> Self {
+        self.client = self.client.with_compute_budget(compute_budget);
+        self
+    }
+
+    /// Returns the async client this client runs.
+    pub fn nonblocking(&self) -> &nonblocking::MTreeClient {
+        &self.client
+    }
+
+    pub fn migrate_info(&self, payer: &Keypair) -> Result<Signature, Error> {
+        self.invoke(self.client.migrate_info(payer))
+    }
+
+    pub fn get_info(&self) -> Result<MTreeInfo, Error> {
+        self.invoke(self.client.get_info())
     }
 
     pub fn get_root_hash(&self) -> Result<Hash, Error> {
-        let mtree_info = self.get_info()?;
-        Ok(mtree_info.root_hash)
+        self.invoke(self.client.get_root_hash())
+    }
+
+    pub fn get_leaf_count(&self) -> Result<u64, Error> {
+        self.invoke(self.client.get_leaf_count())
+    }
+
+    pub fn get_leaf_proof(&self, leaf_index: u64) -> Result<MerkleProof, Error> {
+        self.invoke(self.client.get_leaf_proof(leaf_index))
+    }
+
+    pub fn get_leaves_proof(&self, leaf_indexes: &[u64]) -> Result<MerkleMultiProof, Error> {
+        self.invoke(self.client.get_leaves_proof(leaf_indexes))
+    }
+
+    pub fn load_tree(&self) -> Result<LocalTree, Error> {
+        self.invoke(self.client.load_tree())
+    }
+
+    pub fn audit(&self) -> Result<Vec<AuditIssue>, Error> {
+        self.invoke(self.client.audit())
+    }
+
+    pub fn get_leaf_marker(&self, leaf_hash: &Hash) -> Result<Option<LeafMarker>, Error> {
+        self.invoke(self.client.get_leaf_marker(leaf_hash))
+    }
+
+    pub fn init_tree(
+        &self,
+        payer: &Keypair,
+        upgrade_authority: &Keypair,
+        config: TreeConfig,
+    ) -> Result<Signature, Error> {
+        self.invoke(self.client.init_tree(payer, upgrade_authority, config))
     }
 
     pub fn insert_leaf(&self, payer: &Keypair, data: Vec<u8>) -> Result<Signature, Error> {
-        let tree_id = self.get_info().map(|i| i.tree_id).unwrap_or_default();
+        self.invoke(self.client.insert_leaf(payer, data))
+    }
+
+    pub fn simulate_insert(
+        &self,
+        payer: &Keypair,
+        data: Vec<u8>,
+    ) -> Result<nonblocking::SimulatedInsert, Error> {
+        self.invoke(self.client.simulate_insert(payer, data))
+    }
+
+    pub fn insert_authored_leaf(
+        &self,
+        payer: &Keypair,
+        author: &Keypair,
+        data: Vec<u8>,
+    ) -> Result<Signature, Error> {
+        self.invoke(self.client.insert_authored_leaf(payer, author, data))
+    }
 
-        let insert_ix =
-            make_insert_leaf_instruction(self.program_id, payer.pubkey(), data, tree_id)?;
+    pub fn get_signed_leaf_message(&self, nonce: u64, data: &[u8]) -> Vec<u8> {
+        self.client.get_signed_leaf_message(nonce, data)
+    }
 
-        let recent_blockhash = self.client.get_latest_blockhash()?;
-        let transaction = Transaction::new_signed_with_payer(
-            &[insert_ix],
-            Some(&payer.pubkey()),
-            &[payer],
-            recent_blockhash,
-        );
+    pub fn get_signed_leaf_marker(
+        &self,
+        message: &[u8],
+    ) -> Result<Option<SignedLeafMarker>, Error> {
+        self.invoke(self.client.get_signed_leaf_marker(message))
+    }
+
+    pub fn insert_signed_leaf(
+        &self,
+        payer: &Keypair,
+        signer: Pubkey,
+        signature: Signature,
+        nonce: u64,
+        data: Vec<u8>,
+    ) -> Result<Signature, Error> {
+        self.invoke(
+            self.client
+                .insert_signed_leaf(payer, signer, signature, nonce, data),
+        )
+    }
+
+    pub fn insert_large_leaf(&self, payer: &Keypair, data: &[u8]) -> Result<Signature, Error> {
+        self.invoke(self.client.insert_large_leaf(payer, data))
+    }
+
+    pub fn init_indexed_tree(&self, payer: &Keypair) -> Result<Signature, Error> {
+        self.invoke(self.client.init_indexed_tree(payer))
+    }
 
-        Ok(self.client.send_and_confirm_transaction(&transaction)?)
+    pub fn get_indexed_tree(&self) -> Result<IndexedTree, Error> {
+        self.invoke(self.client.get_indexed_tree())
+    }
+
+    pub fn get_indexed_leaves(&self) -> Result<IndexedTreeLeaves, Error> {
+        self.invoke(self.client.get_indexed_leaves())
+    }
+
+    pub fn insert_indexed(&self, payer: &Keypair, value: Hash) -> Result<Signature, Error> {
+        self.invoke(self.client.insert_indexed(payer, value))
+    }
+
+    pub fn get_membership_proof(&self, value: &Hash) -> Result<IndexedLeafProof, Error> {
+        self.invoke(self.client.get_membership_proof(value))
+    }
+
+    pub fn get_non_membership_proof(&self, value: &Hash) -> Result<IndexedLeafProof, Error> {
+        self.invoke(self.client.get_non_membership_proof(value))
+    }
+
+    pub fn init_sparse_tree(
+        &self,
+        payer: &Keypair,
+        authority: &Keypair,
+    ) -> Result<Signature, Error> {
+        self.invoke(self.client.init_sparse_tree(payer, authority))
+    }
+
+    pub fn get_sparse_tree(&self) -> Result<SparseTree, Error> {
+        self.invoke(self.client.get_sparse_tree())
+    }
+
+    pub fn get_sparse_keys(&self) -> Result<Vec<SparseKey>, Error> {
+        self.invoke(self.client.get_sparse_keys())
+    }
+
+    pub fn get_sparse_entries(&self) -> Result<SparseTreeEntries, Error> {
+        self.invoke(self.client.get_sparse_entries())
+    }
+
+    pub fn get_sparse_proof(&self, key: &SparseKey) -> Result<(Option<Hash>, SparseProof), Error> {
+        self.invoke(self.client.get_sparse_proof(key))
+    }
+
+    pub fn set_sparse_value(
+        &self,
+        payer: &Keypair,
+        authority: &Keypair,
+        key: SparseKey,
+        value: Hash,
+    ) -> Result<Signature, Error> {
+        self.invoke(self.client.set_sparse_value(payer, authority, key, value))
+    }
+
+    pub fn delete_sparse_value(
+        &self,
+        payer: &Keypair,
+        authority: &Keypair,
+        key: SparseKey,
+    ) -> Result<Signature, Error> {
+        self.invoke(self.client.delete_sparse_value(payer, authority, key))
+    }
+
+    pub fn get_mmr(&self) -> Result<Mmr, Error> {
+        self.invoke(self.client.get_mmr())
+    }
+
+    pub fn append_mmr_leaf(&self, payer: &Keypair, data: Vec<u8>) -> Result<Signature, Error> {
+        self.invoke(self.client.append_mmr_leaf(payer, data))
+    }
+
+    pub fn get_mmr_leaves(&self) -> Result<MmrLeaves, Error> {
+        self.invoke(self.client.get_mmr_leaves())
+    }
+
+    pub fn get_mmr_proof(&self, leaf_index: u64) -> Result<MmrProof, Error> {
+        self.invoke(self.client.get_mmr_proof(leaf_index))
+    }
+
+    pub fn get_mmr_consistency_proof(
+        &self,
+        old_leaf_count: u64,
+        new_leaf_count: u64,
+    ) -> Result<MmrConsistencyProof, Error> {
+        self.invoke(
+            self.client
+                .get_mmr_consistency_proof(old_leaf_count, new_leaf_count),
+        )
+    }
+
+    pub fn get_receipt(&self, tx: Signature) -> Result<ProofReceipt, Error> {
+        self.invoke(self.client.get_receipt(tx))
     }
 
     pub fn get_tx_root_hash(&self, tx: Signature) -> Result<Hash, Error> {
-        let tx = self
-            .client
-            .get_transaction(&tx, UiTransactionEncoding::Json)?;
-        let meta = tx
-            .transaction
-            .meta
-            .ok_or_else(|| eyre::eyre!("No transaction meta"))?;
-        let logs = meta
-            .log_messages
-            .ok_or_else(|| eyre::eyre!("No log messages"))?;
-
-        let log = logs
-            .iter()
-            .filter_map(|log| log.strip_prefix("Program log: "))
-            .filter_map(MTreeEvent::decode)
-            .next()
-            .ok_or_else(|| eyre::eyre!("No MTreeEvent"))?;
-
-        let MTreeEvent::NewRootHash(hash) = log;
-        Ok(hash)
+        self.invoke(self.client.get_tx_root_hash(tx))
+    }
+
+    fn invoke<T>(&self, f: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        // Unlike `block_on`, `block_in_place` can be called from a multi
+        // threaded runtime, like the blocking RPC client does.
+        tokio::task::block_in_place(move || self.runtime.block_on(f))
     }
 }
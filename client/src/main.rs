@@ -1,31 +1,94 @@
-use clap::{command, Parser};
+use clap::{command, Parser, ValueEnum};
+use client::compute_budget::{ComputeBudget, ComputeUnitLimit};
 use client::MTreeClient;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use solana_program_mtree::info::{
+    find_info_pda, find_sub_tree_pda, LeafMode, LeafPolicy, TreeConfig,
+};
+use solana_program_mtree::mtree::audit::AuditIssue;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
 use std::{fs, process::exit};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 enum Args {
+    /// Create the Merkle tree with a custom config, signed by the upgrade authority
+    InitTree(InitTreeArgs),
+    /// Convert an info account written by an older program to the current layout
+    MigrateInfo(MigrateInfoArgs),
     /// Insert a leaf into the Merkle tree
     InsertLeaf(InsertLeafArgs),
     RootHash(RootHashArgs),
+    /// Print the proof receipt of the leaf inserted by a transaction
+    Receipt(ReceiptArgs),
+    /// Check every sub tree account against its leaves, its parent and the root
+    Audit(AuditArgs),
 }
 
 impl Args {
     fn program_id(&self) -> Pubkey {
         match self {
+            Args::InitTree(args) => args.program_id,
+            Args::MigrateInfo(args) => args.program_id,
             Args::InsertLeaf(args) => args.program_id,
             Args::RootHash(args) => args.program_id,
+            Args::Receipt(args) => args.program_id,
+            Args::Audit(args) => args.program_id,
         }
     }
     fn config_file(&self) -> Option<&str> {
         match self {
+            Args::InitTree(args) => args.config_file.as_deref(),
+            Args::MigrateInfo(args) => args.config_file.as_deref(),
             Args::InsertLeaf(args) => args.config_file.as_deref(),
             Args::RootHash(args) => args.config_file.as_deref(),
+            Args::Receipt(args) => args.config_file.as_deref(),
+            Args::Audit(args) => args.config_file.as_deref(),
         }
     }
 }
 
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct InitTreeArgs {
+    #[arg(short, long)]
+    program_id: Pubkey,
+    #[arg(short, long)]
+    config_file: Option<String>,
+    /// Bind every leaf to the pubkey of its author
+    #[arg(long)]
+    author_bound: bool,
+    /// Off-chain signer allowed to sign leaves for InsertSignedLeaf
+    #[arg(long)]
+    leaf_signer: Option<Pubkey>,
+    /// Minimum leaf length in bytes
+    #[arg(long, default_value_t = 0)]
+    min_leaf_len: u32,
+    /// Maximum leaf length in bytes, 0 for no limit
+    #[arg(long, default_value_t = 0)]
+    max_leaf_len: u32,
+    /// Reject empty leaves
+    #[arg(long)]
+    reject_empty: bool,
+    /// Require every leaf to have this length in bytes
+    #[arg(long, default_value_t = 0)]
+    fixed_leaf_len: u32,
+    /// Reject leaves that are already in the tree
+    #[arg(long)]
+    dedup: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct MigrateInfoArgs {
+    #[arg(short, long)]
+    program_id: Pubkey,
+    #[arg(short, long)]
+    config_file: Option<String>,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct InsertLeafArgs {
@@ -33,9 +96,34 @@ struct InsertLeafArgs {
     program_id: Pubkey,
     #[arg(short, long)]
     config_file: Option<String>,
+    /// Compute unit limit of the transaction
+    #[arg(long)]
+    compute_unit_limit: Option<u32>,
+    /// Set the compute unit limit from a simulation of the transaction
+    #[arg(long, conflicts_with = "compute_unit_limit")]
+    estimate_compute_units: bool,
+    /// Priority fee in micro-lamports per compute unit
+    #[arg(long)]
+    compute_unit_price: Option<u64>,
+    /// Only simulate the insert and print its outcome
+    #[arg(long)]
+    simulate: bool,
     data: String,
 }
 
+impl InsertLeafArgs {
+    fn compute_budget(&self) -> ComputeBudget {
+        ComputeBudget {
+            unit_limit: match self.compute_unit_limit {
+                Some(units) => ComputeUnitLimit::Fixed(units),
+                None if self.estimate_compute_units => ComputeUnitLimit::Estimate,
+                None => ComputeUnitLimit::Default,
+            },
+            unit_price: self.compute_unit_price,
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct RootHashArgs {
@@ -45,6 +133,34 @@ struct RootHashArgs {
     config_file: Option<String>,
 }
 
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct ReceiptArgs {
+    #[arg(short, long)]
+    program_id: Pubkey,
+    #[arg(short, long)]
+    config_file: Option<String>,
+    #[arg(long, value_enum, default_value_t = ReceiptFormat::Json)]
+    format: ReceiptFormat,
+    signature: Signature,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct AuditArgs {
+    #[arg(short, long)]
+    program_id: Pubkey,
+    #[arg(short, long)]
+    config_file: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReceiptFormat {
+    Json,
+    Base58,
+    Base64,
+}
+
 fn main() {
     let args = Args::parse();
     let program_id = args.program_id();
@@ -53,6 +169,35 @@ fn main() {
     let client = MTreeClient::new(program_id, &config.json_rpc_url);
 
     match args {
+        Args::InitTree(args) => {
+            let config = TreeConfig {
+                leaf_mode: if args.author_bound {
+                    LeafMode::AuthorBound
+                } else {
+                    LeafMode::Plain
+                },
+                leaf_signer: args.leaf_signer.unwrap_or_default(),
+                leaf_policy: LeafPolicy {
+                    min_len: args.min_leaf_len,
+                    max_len: args.max_leaf_len,
+                    reject_empty: args.reject_empty,
+                    fixed_len: args.fixed_leaf_len,
+                },
+                dedup: args.dedup,
+            };
+            let tx = client.init_tree(&key, &key, config).unwrap_or_else(|err| {
+                eprintln!("error: Failed to init tree: {:#}", err);
+                exit(1);
+            });
+            println!("Transaction signature: {:#}", tx);
+        }
+        Args::MigrateInfo(_) => {
+            let tx = client.migrate_info(&key).unwrap_or_else(|err| {
+                eprintln!("error: Failed to migrate info: {:#}", err);
+                exit(1);
+            });
+            println!("Transaction signature: {:#}", tx);
+        }
         Args::InsertLeaf(args) => {
             let data = if args.data.starts_with("0x") {
                 hex::decode(&args.data[2..]).unwrap_or_else(|_| {
@@ -63,6 +208,18 @@ fn main() {
                 args.data.as_bytes().to_vec()
             };
 
+            let client = client.with_compute_budget(args.compute_budget());
+            if args.simulate {
+                let simulated = client.simulate_insert(&key, data).unwrap_or_else(|err| {
+                    eprintln!("error: Failed to simulate insert: {:#}", err);
+                    exit(1);
+                });
+                println!("Leaf index: {}", simulated.leaf_index);
+                println!("Root hash: {:#}", hex::encode(simulated.root_hash));
+                println!("Compute units: {}", simulated.units_consumed);
+                return;
+            }
+
             let tx = client.insert_leaf(&key, data).unwrap_or_else(|err| {
                 eprintln!("error: Failed to insert leaf:{:#}", err);
                 exit(1);
@@ -82,6 +239,53 @@ fn main() {
             });
             println!("Root hash: {:#}", hex::encode(hash));
         }
+        Args::Receipt(args) => {
+            let receipt = client.get_receipt(args.signature).unwrap_or_else(|err| {
+                eprintln!("error: Failed to get receipt: {:#}", err);
+                exit(1);
+            });
+            let encoded = match args.format {
+                ReceiptFormat::Json => receipt.to_json(),
+                ReceiptFormat::Base58 => receipt.to_base58(),
+                ReceiptFormat::Base64 => receipt.to_base64(),
+            };
+            let encoded = encoded.unwrap_or_else(|err| {
+                eprintln!("error: Failed to encode receipt: {:#}", err);
+                exit(1);
+            });
+            println!("{}", encoded);
+        }
+        Args::Audit(_) => {
+            let issues = client.audit().unwrap_or_else(|err| {
+                eprintln!("error: Failed to audit tree: {:#}", err);
+                exit(1);
+            });
+            if issues.is_empty() {
+                println!("No issues found");
+                return;
+            }
+            for issue in &issues {
+                let account = match issue.sub_tree_id() {
+                    Some(id) => find_sub_tree_pda(id, &program_id).0,
+                    None => find_info_pda(&program_id).0,
+                };
+                let description = match issue {
+                    AuditIssue::MissingSubTree(id) => format!("sub tree {} does not exist", id),
+                    AuditIssue::InvalidSubTree(id) => format!("sub tree {} cannot be decoded", id),
+                    AuditIssue::InconsistentNodes(id) => {
+                        format!("sub tree {} nodes do not match its leaves", id)
+                    }
+                    AuditIssue::ParentMismatch(id) => {
+                        format!("sub tree {} root does not match its parent leaf", id)
+                    }
+                    AuditIssue::RootMismatch => {
+                        "root sub tree does not match the info root".to_string()
+                    }
+                };
+                println!("{}: {}", account, description);
+            }
+            exit(1);
+        }
     }
 }
 
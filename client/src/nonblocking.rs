@@ -0,0 +1,1003 @@
+use crate::backend::{MTreeBackend, TransactionSimulation};
+use crate::compute_budget::{
+    estimated_unit_limit, ComputeBudget, ComputeUnitLimit, MAX_COMPUTE_UNIT_LIMIT,
+};
+use crate::{MTreeClientError as Error, BUFFER_CHUNK_SIZE};
+use borsh::BorshDeserialize as _;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::instruction::Instruction;
+use solana_program::pubkey::Pubkey;
+use solana_program_mtree::error::MtreeError;
+use solana_program_mtree::events::MTreeEvent;
+use solana_program_mtree::info::{
+    find_indexed_leaf_pda, find_indexed_tree_pda, find_info_pda, find_leaf_marker_pda,
+    find_mmr_page_pda, find_signed_leaf_pda, find_sparse_entry_pda, find_sparse_slot_pda,
+    find_sparse_tree_pda, find_sub_tree_pda, signed_leaf_message, signed_leaf_message_hash,
+    LeafMarker, LeafMode, LegacyMTreeInfo, MTreeInfo, SignedLeafMarker, TreeConfig,
+};
+use solana_program_mtree::instruction::encode::{
+    add_leaf_marker_account, add_next_sub_tree_accounts, make_append_mmr_leaf_instruction,
+    make_close_buffer_instruction, make_delete_sparse_value_instruction,
+    make_init_buffer_instruction, make_init_indexed_tree_instruction,
+    make_init_sparse_tree_instruction, make_init_tree_instruction,
+    make_insert_authored_leaf_instruction, make_insert_from_buffer_instruction,
+    make_insert_indexed_instruction, make_insert_leaf_instruction,
+    make_insert_signed_leaf_instructions, make_migrate_info_instruction,
+    make_set_sparse_value_instruction, make_write_buffer_instruction,
+};
+use solana_program_mtree::mtree::audit::{audit_sub_trees, AuditIssue};
+use solana_program_mtree::mtree::indexed::{
+    IndexedLeaf, IndexedLeafProof, IndexedTree, IndexedTreeLeaves,
+};
+use solana_program_mtree::mtree::local::LocalTree;
+use solana_program_mtree::mtree::mmr::{
+    Mmr, MmrConsistencyProof, MmrLeaves, MmrProof, MMR_PAGE_LEAVES,
+};
+use solana_program_mtree::mtree::path::{get_leaf_position, get_path_to_root};
+use solana_program_mtree::mtree::proof::{MerkleMultiProof, MerkleProof};
+use solana_program_mtree::mtree::sparse::{
+    SparseEntry, SparseKey, SparseProof, SparseSlot, SparseTree, SparseTreeEntries,
+};
+use solana_program_mtree::mtree::sub_tree::SubTree;
+use solana_program_mtree::mtree::{hash_authored_leaf, hash_leaf, Hash, SubTreeId};
+use solana_program_mtree::receipt::ProofReceipt;
+use solana_program_mtree::return_data::InsertLeafReturn;
+use solana_sdk::account::Account;
+use solana_sdk::signature::Signature;
+use solana_sdk::{signature::Keypair, signer::Signer, transaction::Transaction};
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem::size_of;
+
+/// Outcome of a simulated insert.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SimulatedInsert {
+    pub leaf_index: u64,
+    pub leaf_hash: Hash,
+    /// Root of the tree once the leaf is inserted.
+    pub root_hash: Hash,
+    pub units_consumed: u64,
+}
+
+/// Number of times an insert is rebuilt from fresh tree info after other
+/// inserts moved the tree past the sub trees it was built for.
+const INSERT_RETRIES: usize = 3;
+
+/// Client of the mtree program for use from async code, on top of any
+/// `MTreeBackend`. `crate::MTreeClient` wraps it for blocking code.
+pub struct MTreeClient<B = RpcClient> {
+    program_id: Pubkey,
+    backend: B,
+    compute_budget: ComputeBudget,
+}
+
+impl MTreeClient {
+    pub fn new(program_id: Pubkey, url: &str) -> Self {
+        Self::with_backend(program_id, RpcClient::new(url.to_string()))
+    }
+}
+
+impl<B: MTreeBackend> MTreeClient<B> {
+    pub fn with_backend(program_id: Pubkey, backend: B) -> Self {
+        Self {
+            program_id,
+            backend,
+            compute_budget: ComputeBudget::default(),
+        }
+    }
+
+    /// Sets the compute budget of the transactions sent by the client.
+    pub fn with_compute_budget(mut self, compute_budget: ComputeBudget) -> Self {
+        self.compute_budget = compute_budget;
+        self
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    async fn get_account(&self, key: &Pubkey) -> Result<Account, Error> {
+        self.backend
+            .get_account(key)
+            .await?
+            .ok_or(Error::AccountNotFound(*key))
+    }
+
+    pub async fn get_info(&self) -> Result<MTreeInfo, Error> {
+        let (info_pda, _) = find_info_pda(&self.program_id);
+        let account = self
+            .backend
+            .get_account(&info_pda)
+            .await?
+            .ok_or(Error::UninitializedTree)?;
+        if account.data.len() == LegacyMTreeInfo::LEN {
+            return Err(Error::Program(MtreeError::InfoNotMigrated));
+        }
+        let mtree_info = MTreeInfo::try_from_slice(&account.data)?;
+        Ok(mtree_info)
+    }
+
+    /// Returns the info of the tree, or the default info if the tree is not
+    /// created yet, as the first insert creates it.
+    async fn get_info_or_default(&self) -> Result<MTreeInfo, Error> {
+        match self.get_info().await {
+            Err(Error::UninitializedTree) => Ok(MTreeInfo::default()),
+            info => info,
+        }
+    }
+
+    pub async fn get_root_hash(&self) -> Result<Hash, Error> {
+        let mtree_info = self.get_info().await?;
+        Ok(mtree_info.root_hash)
+    }
+
+    pub async fn get_leaf_count(&self) -> Result<u64, Error> {
+        let mtree_info = self.get_info().await?;
+        Ok(mtree_info.leaf_count)
+    }
+
+    /// Returns the proof of the leaf with the given global index against the
+    /// current root.
+    pub async fn get_leaf_proof(&self, leaf_index: u64) -> Result<MerkleProof, Error> {
+        self.leaf_proof(&self.get_info().await?, leaf_index).await
+    }
+
+    async fn leaf_proof(&self, info: &MTreeInfo, leaf_index: u64) -> Result<MerkleProof, Error> {
+        let (id, position) = info
+            .leaf_position(leaf_index)
+            .ok_or(Error::LeafNotFound(leaf_index))?;
+
+        let path = get_path_to_root(id);
+        let sub_trees = self.get_sub_trees(&path).await?;
+
+        MerkleProof::from_sub_trees(&path, &sub_trees, position)
+            .ok_or(Error::Inconsistent("sub trees do not hold the leaf"))
+    }
+
+    /// Returns one proof of the leaves with the given global indexes against
+    /// the current root, each shared sibling stored once.
+    pub async fn get_leaves_proof(&self, leaf_indexes: &[u64]) -> Result<MerkleMultiProof, Error> {
+        let info = self.get_info().await?;
+        let positions = leaf_indexes
+            .iter()
+            .map(|leaf_index| {
+                info.leaf_position(*leaf_index)
+                    .ok_or(Error::LeafNotFound(*leaf_index))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let ids = positions
+            .iter()
+            .flat_map(|(id, _)| get_path_to_root(*id))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let sub_trees = ids
+            .iter()
+            .copied()
+            .zip(self.get_sub_trees(&ids).await?)
+            .collect::<BTreeMap<_, _>>();
+
+        let proofs = positions
+            .iter()
+            .map(|(id, position)| {
+                let path = get_path_to_root(*id);
+                let path_sub_trees = path
+                    .iter()
+                    .map(|id| sub_trees[id].clone())
+                    .collect::<Vec<_>>();
+                MerkleProof::from_sub_trees(&path, &path_sub_trees, *position)
+                    .ok_or(Error::Inconsistent("sub trees do not hold the leaf"))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        MerkleMultiProof::from_proofs(&proofs)
+            .ok_or_else(|| Error::InvalidArgument("no leaves to prove".to_string()))
+    }
+
+    async fn get_sub_trees(&self, ids: &[SubTreeId]) -> Result<Vec<SubTree>, Error> {
+        let keys = ids
+            .iter()
+            .map(|id| find_sub_tree_pda(*id, &self.program_id).0)
+            .collect::<Vec<_>>();
+
+        self.backend
+            .get_multiple_accounts(&keys)
+            .await?
+            .into_iter()
+            .zip(&keys)
+            .map(|(account, key)| {
+                let account = account.ok_or(Error::AccountNotFound(*key))?;
+                Ok(SubTree::try_from_slice(&account.data)?)
+            })
+            .collect()
+    }
+
+    /// Fetches every sub tree and rebuilds the tree in memory from their
+    /// leaves, checking the recomputed root against the info.
+    pub async fn load_tree(&self) -> Result<LocalTree, Error> {
+        let info = self.get_info().await?;
+        let sub_trees = self.load_sub_trees(&info).await?;
+
+        let leaves = (0..info.leaf_count)
+            .map(|leaf_index| {
+                let (id, position) = get_leaf_position(leaf_index, info.leaf_count);
+                sub_trees.get(id as usize)?.get_leaf(position)
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::Inconsistent("sub trees do not hold every leaf"))?;
+
+        let tree = LocalTree::from_leaves(leaves);
+        if tree.root_hash() != info.root_hash || tree.tree_id() != info.tree_id {
+            return Err(Error::Inconsistent("sub trees do not match the root"));
+        }
+        Ok(tree)
+    }
+
+    /// Fetches the sub trees from 0 up to `info.tree_id`. The last one is
+    /// left out if the insert that creates it did not happen yet.
+    async fn load_sub_trees(&self, info: &MTreeInfo) -> Result<Vec<SubTree>, Error> {
+        let keys = (0..=info.tree_id)
+            .map(|id| find_sub_tree_pda(id, &self.program_id).0)
+            .collect::<Vec<_>>();
+        let accounts = self.backend.get_multiple_accounts(&keys).await?;
+
+        let mut sub_trees = Vec::with_capacity(accounts.len());
+        for (id, (account, key)) in (0..).zip(accounts.into_iter().zip(&keys)) {
+            match account {
+                Some(account) => sub_trees.push(SubTree::try_from_slice(&account.data)?),
+                None if id == info.tree_id => {}
+                None => return Err(Error::AccountNotFound(*key)),
+            }
+        }
+        Ok(sub_trees)
+    }
+
+    /// Checks every sub tree account against a recomputation from its leaves,
+    /// against its parent and against the root of the info.
+    pub async fn audit(&self) -> Result<Vec<AuditIssue>, Error> {
+        let info = self.get_info().await?;
+        let keys = (0..=info.tree_id)
+            .map(|id| find_sub_tree_pda(id, &self.program_id).0)
+            .collect::<Vec<_>>();
+        let accounts = self.backend.get_multiple_accounts(&keys).await?;
+
+        let data = accounts
+            .iter()
+            .map(|account| account.as_ref().map(|account| account.data.as_slice()))
+            .collect::<Vec<_>>();
+        Ok(audit_sub_trees(info.tree_id, &info.root_hash, &data))
+    }
+
+    /// Returns the marker of a leaf, which exists only in trees with `dedup`
+    /// enabled.
+    pub async fn get_leaf_marker(&self, leaf_hash: &Hash) -> Result<Option<LeafMarker>, Error> {
+        let (marker_pda, _) = find_leaf_marker_pda(leaf_hash, &self.program_id);
+        self.backend
+            .get_account(&marker_pda)
+            .await?
+            .map(|account| Ok(LeafMarker::try_from_slice(&account.data)?))
+            .transpose()
+    }
+
+    /// Creates the tree with `config`, signed by the upgrade authority of
+    /// the program.
+    pub async fn init_tree(
+        &self,
+        payer: &Keypair,
+        upgrade_authority: &Keypair,
+        config: TreeConfig,
+    ) -> Result<Signature, Error> {
+        let init_ix = make_init_tree_instruction(
+            self.program_id,
+            payer.pubkey(),
+            upgrade_authority.pubkey(),
+            config,
+        )?;
+        self.send_transaction(&[init_ix], payer, &[payer, upgrade_authority])
+            .await
+    }
+
+    /// Converts an info account written in the legacy layout, see
+    /// `MTreeInstruction::MigrateInfo`.
+    pub async fn migrate_info(&self, payer: &Keypair) -> Result<Signature, Error> {
+        let account = self.get_account(&find_info_pda(&self.program_id).0).await?;
+        if account.data.len() != LegacyMTreeInfo::LEN {
+            return Err(Error::Program(MtreeError::InfoAlreadyMigrated));
+        }
+        let legacy = LegacyMTreeInfo::try_from_slice(&account.data)?;
+        let migrate_ix =
+            make_migrate_info_instruction(self.program_id, payer.pubkey(), legacy.tree_id)?;
+        self.send_transaction(&[migrate_ix], payer, &[payer]).await
+    }
+
+    pub async fn insert_leaf(&self, payer: &Keypair, data: Vec<u8>) -> Result<Signature, Error> {
+        self.send_insert(
+            |info| Ok(vec![self.insert_leaf_instruction(info, payer, &data)?]),
+            payer,
+            &[payer],
+        )
+        .await
+    }
+
+    /// Runs `insert_leaf` through simulation and returns its outcome,
+    /// without committing anything.
+    pub async fn simulate_insert(
+        &self,
+        payer: &Keypair,
+        data: Vec<u8>,
+    ) -> Result<SimulatedInsert, Error> {
+        let info = self.get_info_or_default().await?;
+        let mut insert_ix = self.insert_leaf_instruction(&info, payer, &data)?;
+        add_next_sub_tree_accounts(&mut insert_ix, info.tree_id);
+
+        let unit_limit = match self.compute_budget.unit_limit {
+            ComputeUnitLimit::Fixed(units) => units,
+            _ => MAX_COMPUTE_UNIT_LIMIT,
+        };
+        let simulation = self
+            .simulate_transaction(&[insert_ix], payer, &[payer], Some(unit_limit))
+            .await?;
+
+        let (_, return_data) = simulation
+            .logs
+            .return_data
+            .filter(|(program_id, _)| *program_id == self.program_id)
+            .ok_or(Error::MissingEvent("insert return data"))?;
+        let inserted = InsertLeafReturn::from_return_data(&return_data)?;
+
+        Ok(SimulatedInsert {
+            leaf_index: inserted.leaf_index,
+            leaf_hash: inserted.leaf_hash,
+            root_hash: inserted.root_hash,
+            units_consumed: simulation.units_consumed,
+        })
+    }
+
+    fn insert_leaf_instruction(
+        &self,
+        info: &MTreeInfo,
+        payer: &Keypair,
+        data: &[u8],
+    ) -> Result<Instruction, Error> {
+        let mut insert_ix = make_insert_leaf_instruction(
+            self.program_id,
+            payer.pubkey(),
+            payer.pubkey(),
+            data.to_vec(),
+            info.tree_id,
+        )?;
+        if let Some(leaf_hash) = leaf_marker_hash(info, &payer.pubkey(), data, false) {
+            add_leaf_marker_account(&mut insert_ix, &leaf_hash);
+        }
+        Ok(insert_ix)
+    }
+
+    /// Inserts a leaf bound to `author`. The transaction fee, rent and
+    /// commission are paid by `payer`, so a relayer can sponsor the insert.
+    pub async fn insert_authored_leaf(
+        &self,
+        payer: &Keypair,
+        author: &Keypair,
+        data: Vec<u8>,
+    ) -> Result<Signature, Error> {
+        self.send_insert(
+            |info| {
+                let mut insert_ix = make_insert_authored_leaf_instruction(
+                    self.program_id,
+                    payer.pubkey(),
+                    author.pubkey(),
+                    data.clone(),
+                    info.tree_id,
+                )?;
+                if let Some(leaf_hash) = leaf_marker_hash(info, &author.pubkey(), &data, true) {
+                    add_leaf_marker_account(&mut insert_ix, &leaf_hash);
+                }
+                Ok(vec![insert_ix])
+            },
+            payer,
+            &[payer, author],
+        )
+        .await
+    }
+
+    /// Returns the message the `leaf_signer` of the tree must sign to approve
+    /// one signed insert of `data`. Each message is accepted once, so a leaf
+    /// approved several times needs a different `nonce` per approval.
+    pub fn get_signed_leaf_message(&self, nonce: u64, data: &[u8]) -> Vec<u8> {
+        let (info_pda, _) = find_info_pda(&self.program_id);
+        signed_leaf_message(&info_pda, nonce, data)
+    }
+
+    /// Returns the replay marker of a signed leaf message, which exists once
+    /// the message was inserted.
+    pub async fn get_signed_leaf_marker(
+        &self,
+        message: &[u8],
+    ) -> Result<Option<SignedLeafMarker>, Error> {
+        let (marker_pda, _) =
+            find_signed_leaf_pda(&signed_leaf_message_hash(message), &self.program_id);
+        self.backend
+            .get_account(&marker_pda)
+            .await?
+            .map(|account| Ok(SignedLeafMarker::try_from_slice(&account.data)?))
+            .transpose()
+    }
+
+    /// Inserts a leaf signed off-chain by the `leaf_signer` of the tree, see
+    /// `get_signed_leaf_message`.
+    pub async fn insert_signed_leaf(
+        &self,
+        payer: &Keypair,
+        signer: Pubkey,
+        signature: Signature,
+        nonce: u64,
+        data: Vec<u8>,
+    ) -> Result<Signature, Error> {
+        self.send_insert(
+            |info| {
+                let mut instructions = make_insert_signed_leaf_instructions(
+                    self.program_id,
+                    payer.pubkey(),
+                    payer.pubkey(),
+                    signer,
+                    &signature.into(),
+                    nonce,
+                    data.clone(),
+                    info.tree_id,
+                )?;
+                let marker_hash = leaf_marker_hash(info, &payer.pubkey(), &data, false);
+                if let (Some(leaf_hash), Some(insert_ix)) = (marker_hash, instructions.last_mut()) {
+                    add_leaf_marker_account(insert_ix, &leaf_hash);
+                }
+                Ok(instructions)
+            },
+            payer,
+            &[payer],
+        )
+        .await
+    }
+
+    /// Inserts a leaf too large for a single transaction. The data is hashed
+    /// into a new buffer account in chunks of `BUFFER_CHUNK_SIZE` bytes and
+    /// then inserted, which closes the buffer. If any step fails the buffer
+    /// is closed as well, refunding its rent.
+    pub async fn insert_large_leaf(
+        &self,
+        payer: &Keypair,
+        data: &[u8],
+    ) -> Result<Signature, Error> {
+        let data_len = u32::try_from(data.len())
+            .map_err(|_| Error::InvalidArgument("leaf is too large".to_string()))?;
+        let info = self.get_info_or_default().await?;
+        let nonce = buffer_nonce();
+
+        let init_ix = make_init_buffer_instruction(
+            self.program_id,
+            payer.pubkey(),
+            payer.pubkey(),
+            nonce,
+            data_len,
+            info.config.leaf_mode == LeafMode::AuthorBound,
+        )?;
+        self.send_transaction(&[init_ix], payer, &[payer]).await?;
+
+        let result = self.insert_buffer(payer, nonce, data).await;
+        if result.is_err() {
+            let close_ix = make_close_buffer_instruction(
+                self.program_id,
+                payer.pubkey(),
+                nonce,
+                payer.pubkey(),
+            )?;
+            // The error of the upload is the one worth reporting.
+            let _ = self.send_transaction(&[close_ix], payer, &[payer]).await;
+        }
+        result
+    }
+
+    /// Writes `data` into the buffer of the payer and `nonce` and inserts it.
+    async fn insert_buffer(
+        &self,
+        payer: &Keypair,
+        nonce: u64,
+        data: &[u8],
+    ) -> Result<Signature, Error> {
+        for (i, chunk) in data.chunks(BUFFER_CHUNK_SIZE).enumerate() {
+            let write_ix = make_write_buffer_instruction(
+                self.program_id,
+                payer.pubkey(),
+                nonce,
+                (i * BUFFER_CHUNK_SIZE) as u32,
+                chunk.to_vec(),
+            )?;
+            self.send_transaction(&[write_ix], payer, &[payer]).await?;
+        }
+
+        self.send_insert(
+            |info| {
+                let mut insert_ix = make_insert_from_buffer_instruction(
+                    self.program_id,
+                    payer.pubkey(),
+                    payer.pubkey(),
+                    nonce,
+                    info.tree_id,
+                )?;
+                if let Some(leaf_hash) = leaf_marker_hash(info, &payer.pubkey(), data, false) {
+                    add_leaf_marker_account(&mut insert_ix, &leaf_hash);
+                }
+                Ok(vec![insert_ix])
+            },
+            payer,
+            &[payer],
+        )
+        .await
+    }
+
+    pub async fn init_indexed_tree(&self, payer: &Keypair) -> Result<Signature, Error> {
+        let init_ix = make_init_indexed_tree_instruction(self.program_id, payer.pubkey())?;
+        self.send_transaction(&[init_ix], payer, &[payer]).await
+    }
+
+    pub async fn get_indexed_tree(&self) -> Result<IndexedTree, Error> {
+        let (tree_pda, _) = find_indexed_tree_pda(&self.program_id);
+        let account = self.get_account(&tree_pda).await?;
+        Ok(IndexedTree::try_from_slice(&account.data)?)
+    }
+
+    /// Fetches all leaves of the indexed tree and checks them against its
+    /// current root.
+    pub async fn get_indexed_leaves(&self) -> Result<IndexedTreeLeaves, Error> {
+        let tree = self.get_indexed_tree().await?;
+        let keys = (0..tree.next_index)
+            .map(|index| find_indexed_leaf_pda(index, &self.program_id).0)
+            .collect::<Vec<_>>();
+
+        let leaves = self
+            .backend
+            .get_multiple_accounts(&keys)
+            .await?
+            .into_iter()
+            .zip(&keys)
+            .map(|(account, key)| {
+                let account = account.ok_or(Error::AccountNotFound(*key))?;
+                Ok(IndexedLeaf::try_from_slice(&account.data)?)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let leaves = IndexedTreeLeaves::new(leaves);
+        if leaves.root() != tree.root {
+            return Err(Error::Inconsistent("indexed leaves do not match the root"));
+        }
+        Ok(leaves)
+    }
+
+    /// Inserts `value` into the indexed tree.
+    pub async fn insert_indexed(&self, payer: &Keypair, value: Hash) -> Result<Signature, Error> {
+        let leaves = self.get_indexed_leaves().await?;
+        let low_leaf_index = leaves
+            .find_low_leaf(&value)
+            .ok_or_else(|| Error::InvalidArgument("value is in the indexed tree".to_string()))?;
+        let proof = leaves
+            .get_proof(low_leaf_index)
+            .ok_or(Error::LeafNotFound(low_leaf_index))?;
+
+        let insert_ix = make_insert_indexed_instruction(
+            self.program_id,
+            payer.pubkey(),
+            value,
+            low_leaf_index,
+            proof.siblings,
+            leaves.leaves().len() as u64,
+        )?;
+        self.send_transaction(&[insert_ix], payer, &[payer]).await
+    }
+
+    /// Returns the proof that `value` is in the indexed tree.
+    pub async fn get_membership_proof(&self, value: &Hash) -> Result<IndexedLeafProof, Error> {
+        self.get_indexed_leaves()
+            .await?
+            .membership_proof(value)
+            .ok_or_else(|| Error::InvalidArgument("value is not in the indexed tree".to_string()))
+    }
+
+    /// Returns the proof that `value` is not in the indexed tree.
+    pub async fn get_non_membership_proof(&self, value: &Hash) -> Result<IndexedLeafProof, Error> {
+        self.get_indexed_leaves()
+            .await?
+            .non_membership_proof(value)
+            .ok_or_else(|| Error::InvalidArgument("value is in the indexed tree".to_string()))
+    }
+
+    pub async fn init_sparse_tree(
+        &self,
+        payer: &Keypair,
+        authority: &Keypair,
+    ) -> Result<Signature, Error> {
+        let init_ix =
+            make_init_sparse_tree_instruction(self.program_id, payer.pubkey(), authority.pubkey())?;
+        self.send_transaction(&[init_ix], payer, &[payer, authority])
+            .await
+    }
+
+    pub async fn get_sparse_tree(&self) -> Result<SparseTree, Error> {
+        let (tree_pda, _) = find_sparse_tree_pda(&self.program_id);
+        let account = self.get_account(&tree_pda).await?;
+        Ok(SparseTree::try_from_slice(&account.data)?)
+    }
+
+    /// Returns the keys of the sparse tree in the order of their slots.
+    pub async fn get_sparse_keys(&self) -> Result<Vec<SparseKey>, Error> {
+        let tree = self.get_sparse_tree().await?;
+        self.get_sparse_slots(&tree).await
+    }
+
+    async fn get_sparse_slots(&self, tree: &SparseTree) -> Result<Vec<SparseKey>, Error> {
+        let slots = (0..tree.key_count)
+            .map(|slot| find_sparse_slot_pda(slot, &self.program_id).0)
+            .collect::<Vec<_>>();
+        self.backend
+            .get_multiple_accounts(&slots)
+            .await?
+            .into_iter()
+            .zip(&slots)
+            .map(|(account, slot)| {
+                let account = account.ok_or(Error::AccountNotFound(*slot))?;
+                Ok(SparseSlot::try_from_slice(&account.data)?.key)
+            })
+            .collect()
+    }
+
+    /// Fetches the entries of every key listed by the slots of the sparse
+    /// tree and checks them against its current root.
+    pub async fn get_sparse_entries(&self) -> Result<SparseTreeEntries, Error> {
+        let tree = self.get_sparse_tree().await?;
+        let keys = self
+            .get_sparse_slots(&tree)
+            .await?
+            .iter()
+            .map(|key| find_sparse_entry_pda(key, &self.program_id).0)
+            .collect::<Vec<_>>();
+        let entries = self
+            .backend
+            .get_multiple_accounts(&keys)
+            .await?
+            .into_iter()
+            .zip(&keys)
+            .map(|(account, key)| {
+                let account = account.ok_or(Error::AccountNotFound(*key))?;
+                Ok(SparseEntry::try_from_slice(&account.data)?)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let entries = SparseTreeEntries::new(entries);
+        if entries.root() != tree.root {
+            return Err(Error::Inconsistent("sparse entries do not match the root"));
+        }
+        Ok(entries)
+    }
+
+    /// Returns the value of `key` with its proof against the current root of
+    /// the sparse tree. The proof of a key without value proves it is empty.
+    pub async fn get_sparse_proof(
+        &self,
+        key: &SparseKey,
+    ) -> Result<(Option<Hash>, SparseProof), Error> {
+        let entries = self.get_sparse_entries().await?;
+        Ok((entries.get(key).copied(), entries.get_proof(key)))
+    }
+
+    pub async fn set_sparse_value(
+        &self,
+        payer: &Keypair,
+        authority: &Keypair,
+        key: SparseKey,
+        value: Hash,
+    ) -> Result<Signature, Error> {
+        let key_count = self.get_sparse_tree().await?.key_count;
+        let (_, proof) = self.get_sparse_proof(&key).await?;
+        let set_ix = make_set_sparse_value_instruction(
+            self.program_id,
+            payer.pubkey(),
+            authority.pubkey(),
+            key_count,
+            key,
+            value,
+            proof,
+        )?;
+        self.send_transaction(&[set_ix], payer, &[payer, authority])
+            .await
+    }
+
+    /// Removes `key` from the sparse tree, the rent of its entry goes to
+    /// `payer`.
+    pub async fn delete_sparse_value(
+        &self,
+        payer: &Keypair,
+        authority: &Keypair,
+        key: SparseKey,
+    ) -> Result<Signature, Error> {
+        let keys = self.get_sparse_keys().await?;
+        let slot = keys
+            .iter()
+            .position(|listed| *listed == key)
+            .ok_or_else(|| Error::InvalidArgument("key is not in the sparse tree".to_string()))?;
+        let (_, proof) = self.get_sparse_proof(&key).await?;
+        let delete_ix = make_delete_sparse_value_instruction(
+            self.program_id,
+            authority.pubkey(),
+            payer.pubkey(),
+            keys.len() as u64,
+            key,
+            slot as u64,
+            proof,
+        )?;
+        self.send_transaction(&[delete_ix], payer, &[payer, authority])
+            .await
+    }
+
+    /// Returns the Merkle mountain range kept in the info account, empty if
+    /// the tree is not created yet.
+    pub async fn get_mmr(&self) -> Result<Mmr, Error> {
+        Ok(self.get_info_or_default().await?.mmr)
+    }
+
+    pub async fn append_mmr_leaf(
+        &self,
+        payer: &Keypair,
+        data: Vec<u8>,
+    ) -> Result<Signature, Error> {
+        let mmr = self.get_mmr().await?;
+        let append_ix = make_append_mmr_leaf_instruction(
+            self.program_id,
+            payer.pubkey(),
+            payer.pubkey(),
+            data,
+            mmr.leaf_count,
+        )?;
+        self.send_transaction(&[append_ix], payer, &[payer]).await
+    }
+
+    /// Reads the leaves of the Merkle mountain range from its page accounts,
+    /// one account per `MMR_PAGE_LEAVES` leaves, and checks them against its
+    /// current root.
+    pub async fn get_mmr_leaves(&self) -> Result<MmrLeaves, Error> {
+        let mmr = self.get_mmr().await?;
+        let keys = (0..mmr.leaf_count.div_ceil(MMR_PAGE_LEAVES))
+            .map(|page| find_mmr_page_pda(page, &self.program_id).0)
+            .collect::<Vec<_>>();
+
+        let mut leaves = Vec::with_capacity(mmr.leaf_count as usize);
+        for (account, key) in self
+            .backend
+            .get_multiple_accounts(&keys)
+            .await?
+            .into_iter()
+            .zip(&keys)
+        {
+            let account = account.ok_or(Error::AccountNotFound(*key))?;
+            leaves.extend(
+                account
+                    .data
+                    .chunks_exact(size_of::<Hash>())
+                    .map(|leaf| Hash::try_from(leaf).expect("chunk of a hash")),
+            );
+        }
+        leaves.truncate(mmr.leaf_count as usize);
+
+        let leaves = MmrLeaves::new(leaves);
+        if leaves.root() != mmr.root {
+            return Err(Error::Inconsistent("mmr leaves do not match the root"));
+        }
+        Ok(leaves)
+    }
+
+    /// Returns the proof of the leaf with the given index against the
+    /// current root of the Merkle mountain range.
+    pub async fn get_mmr_proof(&self, leaf_index: u64) -> Result<MmrProof, Error> {
+        let leaves = self.get_mmr_leaves().await?;
+        leaves
+            .get_proof(leaf_index, leaves.leaves().len() as u64)
+            .ok_or(Error::LeafNotFound(leaf_index))
+    }
+
+    /// Returns the proof that the Merkle mountain range holding the first
+    /// `new_leaf_count` leaves extends the one holding the first
+    /// `old_leaf_count` leaves.
+    pub async fn get_mmr_consistency_proof(
+        &self,
+        old_leaf_count: u64,
+        new_leaf_count: u64,
+    ) -> Result<MmrConsistencyProof, Error> {
+        self.get_mmr_leaves()
+            .await?
+            .get_consistency_proof(old_leaf_count, new_leaf_count)
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "no consistency proof from {} to {} leaves",
+                    old_leaf_count, new_leaf_count
+                ))
+            })
+    }
+
+    /// Sends the instructions built by `build` from the current tree info.
+    /// The insert also carries the sub trees of the next path, and is
+    /// rebuilt up to `INSERT_RETRIES` times if the tree still moved past it.
+    async fn send_insert(
+        &self,
+        build: impl Fn(&MTreeInfo) -> Result<Vec<Instruction>, Error>,
+        payer: &Keypair,
+        signers: &[&Keypair],
+    ) -> Result<Signature, Error> {
+        let mut retries = 0;
+        loop {
+            let info = self.get_info_or_default().await?;
+            let mut instructions = build(&info)?;
+            if let Some(insert_ix) = instructions.last_mut() {
+                add_next_sub_tree_accounts(insert_ix, info.tree_id);
+            }
+
+            match self.send_transaction(&instructions, payer, signers).await {
+                Err(Error::Program(MtreeError::SubTreeFull | MtreeError::InvalidNodeAccount))
+                    if retries < INSERT_RETRIES =>
+                {
+                    retries += 1
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_transaction(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        signers: &[&Keypair],
+    ) -> Result<Signature, Error> {
+        let recent_blockhash = self.backend.get_latest_blockhash().await?;
+        let unit_limit = match self.compute_budget.unit_limit {
+            ComputeUnitLimit::Default => None,
+            ComputeUnitLimit::Fixed(units) => Some(units),
+            ComputeUnitLimit::Estimate => {
+                let simulation = self
+                    .simulate_transaction(
+                        instructions,
+                        payer,
+                        signers,
+                        Some(MAX_COMPUTE_UNIT_LIMIT),
+                    )
+                    .await?;
+                Some(estimated_unit_limit(simulation.units_consumed))
+            }
+        };
+
+        let transaction = Transaction::new_signed_with_payer(
+            &self.with_compute_budget_instructions(instructions, unit_limit),
+            Some(&payer.pubkey()),
+            signers,
+            recent_blockhash,
+        );
+
+        self.backend
+            .send_transaction(&transaction)
+            .await
+            .map_err(|err| Error::from_send_error(err, &transaction, &self.program_id))
+    }
+
+    /// Simulates the instructions with the compute unit price of the client
+    /// and the given unit limit. A failed simulation is returned as an error.
+    async fn simulate_transaction(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        signers: &[&Keypair],
+        unit_limit: Option<u32>,
+    ) -> Result<TransactionSimulation, Error> {
+        let recent_blockhash = self.backend.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &self.with_compute_budget_instructions(instructions, unit_limit),
+            Some(&payer.pubkey()),
+            signers,
+            recent_blockhash,
+        );
+
+        let simulation = self.backend.simulate_transaction(&transaction).await?;
+        if let Err(err) = simulation.result {
+            return Err(Error::from_transaction_error(
+                err,
+                &transaction,
+                &self.program_id,
+            ));
+        }
+        Ok(simulation)
+    }
+
+    fn with_compute_budget_instructions(
+        &self,
+        instructions: &[Instruction],
+        unit_limit: Option<u32>,
+    ) -> Vec<Instruction> {
+        let mut budget_instructions = self.compute_budget.instructions(unit_limit);
+        budget_instructions.extend_from_slice(instructions);
+        budget_instructions
+    }
+
+    /// Builds the receipt of the leaf inserted by the given transaction,
+    /// proving it against the current root.
+    pub async fn get_receipt(&self, tx: Signature) -> Result<ProofReceipt, Error> {
+        let logs = self.backend.get_transaction_logs(&tx).await?;
+        let (_, return_data) = logs
+            .return_data
+            .filter(|(program_id, _)| *program_id == self.program_id)
+            .ok_or(Error::MissingEvent("insert return data"))?;
+        let inserted = InsertLeafReturn::from_return_data(&return_data)?;
+
+        let info = self.get_info().await?;
+        let proof = self.leaf_proof(&info, inserted.leaf_index).await?;
+        if !proof.verify(&info.root_hash, inserted.leaf_hash) {
+            return Err(Error::Inconsistent("tree changed while reading the proof"));
+        }
+
+        Ok(ProofReceipt::new(
+            self.program_id,
+            inserted.leaf_index,
+            inserted.leaf_hash,
+            proof,
+            info.root_hash,
+            tx.into(),
+            logs.slot,
+        ))
+    }
+
+    pub async fn get_tx_root_hash(&self, tx: Signature) -> Result<Hash, Error> {
+        self.get_tx_events(&tx)
+            .await?
+            .into_iter()
+            .find_map(|event| match event {
+                MTreeEvent::NewRootHash(hash) => Some(hash),
+                _ => None,
+            })
+            .ok_or(Error::MissingEvent("NewRootHash event"))
+    }
+
+    async fn get_tx_events(&self, tx: &Signature) -> Result<Vec<MTreeEvent>, Error> {
+        let logs = self.backend.get_transaction_logs(tx).await?;
+
+        Ok(logs
+            .log_messages
+            .iter()
+            .filter_map(|log| log.strip_prefix("Program log: "))
+            .filter_map(MTreeEvent::decode)
+            .collect())
+    }
+}
+
+/// Returns the hash of the leaf if the tree requires a leaf marker account
+/// for it.
+fn leaf_marker_hash(
+    info: &MTreeInfo,
+    author: &Pubkey,
+    data: &[u8],
+    authored: bool,
+) -> Option<Hash> {
+    if !info.config.dedup {
+        return None;
+    }
+
+    if authored || info.config.leaf_mode == LeafMode::AuthorBound {
+        Some(hash_authored_leaf(author, data))
+    } else {
+        Some(hash_leaf(data))
+    }
+}
+
+/// Returns a nonce for a new buffer, distinct from those of the buffers of
+/// the same authority still open.
+fn buffer_nonce() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
@@ -1,5 +1,10 @@
 use crate::error::MtreeError;
-use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, msg};
+use crate::info::find_program_data_pda;
+use solana_instructions_sysvar::{load_current_index_checked, load_instruction_at_checked};
+use solana_program::{
+    account_info::AccountInfo, bpf_loader_upgradeable, ed25519_program, entrypoint::ProgramResult,
+    msg, pubkey::Pubkey,
+};
 
 pub fn assert_signer(account_name: &str, account: &AccountInfo) -> ProgramResult {
     if !account.is_signer {
@@ -25,3 +30,159 @@ pub fn assert_system_program(account: &AccountInfo) -> ProgramResult {
 
     Ok(())
 }
+
+/// Asserts that `authority` signed and is the upgrade authority recorded in
+/// the program data account of the program.
+pub fn assert_upgrade_authority(
+    program_id: &Pubkey,
+    program_data: &AccountInfo,
+    authority: &AccountInfo,
+) -> ProgramResult {
+    assert_signer("upgrade_authority", authority)?;
+
+    if *program_data.key != find_program_data_pda(program_id).0
+        || *program_data.owner != bpf_loader_upgradeable::ID
+    {
+        return Err(MtreeError::InvalidProgramDataAccount.into());
+    }
+
+    let data = program_data.try_borrow_data()?;
+    let upgrade_authority =
+        parse_upgrade_authority(&data).ok_or(MtreeError::InvalidProgramDataAccount)?;
+    if upgrade_authority != Some(*authority.key) {
+        msg!("Account {} is not the upgrade authority", authority.key);
+        return Err(MtreeError::InvalidTreeAuthority.into());
+    }
+
+    Ok(())
+}
+
+/// Returns the upgrade authority stored in a `ProgramData` account of the
+/// upgradeable loader, `Some(None)` for an immutable program.
+fn parse_upgrade_authority(data: &[u8]) -> Option<Option<Pubkey>> {
+    const PROGRAM_DATA_TAG: u32 = 3;
+    // The tag is followed by the deployment slot.
+    const AUTHORITY_OFFSET: usize = 4 + 8;
+
+    let tag = u32::from_le_bytes(data.get(..4)?.try_into().ok()?);
+    if tag != PROGRAM_DATA_TAG {
+        return None;
+    }
+
+    match data.get(AUTHORITY_OFFSET)? {
+        0 => Some(None),
+        1 => {
+            let key = data.get(AUTHORITY_OFFSET + 1..AUTHORITY_OFFSET + 33)?;
+            Some(Some(Pubkey::try_from(key).ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// Asserts that the instruction preceding the current one is an Ed25519
+/// precompile instruction verifying a signature of `message` by `signer`.
+pub fn assert_ed25519_signature(
+    instructions_sysvar: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> ProgramResult {
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    if current_index == 0 {
+        return Err(MtreeError::MissingLeafSignature.into());
+    }
+
+    let instruction = load_instruction_at_checked(current_index as usize - 1, instructions_sysvar)?;
+    if instruction.program_id != ed25519_program::ID {
+        return Err(MtreeError::MissingLeafSignature.into());
+    }
+
+    let (public_key, signed_message) =
+        parse_ed25519_instruction(&instruction.data).ok_or(MtreeError::InvalidLeafSignature)?;
+    if public_key != signer.as_ref() {
+        msg!("Leaf must be signed by {}", signer);
+        return Err(MtreeError::InvalidLeafSignature.into());
+    }
+    if signed_message != message {
+        return Err(MtreeError::InvalidLeafSignature.into());
+    }
+
+    Ok(())
+}
+
+/// Returns the public key and the message of an Ed25519 instruction holding
+/// a single signature with all its data inside the instruction itself.
+fn parse_ed25519_instruction(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    const OFFSETS_START: usize = 2;
+    const OFFSETS_SIZE: usize = 14;
+    const PUBKEY_SIZE: usize = 32;
+    const CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+    if data.len() < OFFSETS_START + OFFSETS_SIZE || data[0] != 1 {
+        return None;
+    }
+
+    let offset = |i: usize| {
+        let start = OFFSETS_START + 2 * i;
+        u16::from_le_bytes([data[start], data[start + 1]])
+    };
+    let signature_instruction_index = offset(1);
+    let public_key_offset = offset(2) as usize;
+    let public_key_instruction_index = offset(3);
+    let message_data_offset = offset(4) as usize;
+    let message_data_size = offset(5) as usize;
+    let message_instruction_index = offset(6);
+
+    if signature_instruction_index != CURRENT_INSTRUCTION
+        || public_key_instruction_index != CURRENT_INSTRUCTION
+        || message_instruction_index != CURRENT_INSTRUCTION
+    {
+        return None;
+    }
+
+    let public_key = data.get(public_key_offset..public_key_offset + PUBKEY_SIZE)?;
+    let message = data.get(message_data_offset..message_data_offset + message_data_size)?;
+    Some((public_key, message))
+}
+
+#[cfg(all(test, feature = "encode"))]
+mod tests {
+    use super::*;
+    use crate::instruction::encode::make_ed25519_instruction;
+
+    #[test]
+    fn test_parse_ed25519_instruction() {
+        let signer = Pubkey::new_unique();
+        let instruction = make_ed25519_instruction(&signer, &[7; 64], b"leaf").unwrap();
+        assert_eq!(instruction.program_id, ed25519_program::ID);
+
+        let (public_key, message) = parse_ed25519_instruction(&instruction.data).unwrap();
+        assert_eq!(public_key, signer.as_ref());
+        assert_eq!(message, b"leaf");
+
+        let mut data = instruction.data.clone();
+        data[4] = 0;
+        assert!(parse_ed25519_instruction(&data).is_none());
+        assert!(parse_ed25519_instruction(&instruction.data[..15]).is_none());
+    }
+
+    #[test]
+    fn test_parse_upgrade_authority() {
+        let authority = Pubkey::new_unique();
+        let mut data = [
+            3u32.to_le_bytes().as_ref(),
+            &[0; 8],
+            &[1],
+            authority.as_ref(),
+        ]
+        .concat();
+        assert_eq!(parse_upgrade_authority(&data), Some(Some(authority)));
+
+        data[12] = 0;
+        assert_eq!(parse_upgrade_authority(&data[..13]), Some(None));
+
+        data[12] = 1;
+        assert_eq!(parse_upgrade_authority(&data[..40]), None);
+        data[0] = 2;
+        assert_eq!(parse_upgrade_authority(&data), None);
+    }
+}
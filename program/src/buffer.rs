@@ -0,0 +1,174 @@
+use crate::mtree::Hash;
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::mem::size_of;
+use sha2::digest::generic_array::GenericArray;
+use solana_program::pubkey::Pubkey;
+
+const SHA256_BLOCK: usize = 64;
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 of data fed chunk by chunk, equal to `hash_leaf` of the whole
+/// data. Unlike the hash syscall, its state can be stored between
+/// transactions.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Eq, PartialEq)]
+pub struct LeafHasher {
+    state: [u32; 8],
+    /// Data not compressed yet, the first `len % 64` bytes.
+    block: [u8; SHA256_BLOCK],
+    len: u64,
+}
+
+impl LeafHasher {
+    pub const LEN: usize = size_of::<[u32; 8]>() + SHA256_BLOCK + size_of::<u64>();
+
+    pub fn new() -> Self {
+        Self {
+            state: SHA256_IV,
+            block: [0; SHA256_BLOCK],
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        let used = (self.len % SHA256_BLOCK as u64) as usize;
+        self.len += data.len() as u64;
+
+        if used > 0 {
+            let take = (SHA256_BLOCK - used).min(data.len());
+            self.block[used..used + take].copy_from_slice(&data[..take]);
+            data = &data[take..];
+            if used + take < SHA256_BLOCK {
+                return;
+            }
+            compress(&mut self.state, &self.block);
+        }
+
+        let mut blocks = data.chunks_exact(SHA256_BLOCK);
+        for block in &mut blocks {
+            compress(&mut self.state, block);
+        }
+        let rest = blocks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+    }
+
+    pub fn finalize(&self) -> Hash {
+        let mut state = self.state;
+        let used = (self.len % SHA256_BLOCK as u64) as usize;
+
+        let mut block = [0; SHA256_BLOCK];
+        block[..used].copy_from_slice(&self.block[..used]);
+        block[used] = 0x80;
+        if used >= SHA256_BLOCK - size_of::<u64>() {
+            compress(&mut state, &block);
+            block = [0; SHA256_BLOCK];
+        }
+        block[SHA256_BLOCK - size_of::<u64>()..].copy_from_slice(&(self.len * 8).to_be_bytes());
+        compress(&mut state, &block);
+
+        let mut hash = Hash::default();
+        for (bytes, word) in hash.chunks_exact_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        hash
+    }
+}
+
+impl Default for LeafHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    sha2::compress256(
+        state,
+        core::slice::from_ref(GenericArray::from_slice(block)),
+    );
+}
+
+/// Account staging a leaf that is too large for a single instruction, at
+/// the PDA of its authority and nonce. Only the hash of the data written so
+/// far is kept, so the cost of an insert does not depend on the leaf size.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, Eq, PartialEq)]
+pub struct LeafBuffer {
+    /// Signer allowed to write the buffer and insert it as a leaf.
+    pub authority: Pubkey,
+    pub nonce: u64,
+    pub bump: u8,
+    pub data_len: u32,
+    /// Bytes written so far. Chunks are written in order.
+    pub written: u32,
+    /// Whether the leaf hash is `H(authority || data)`, as in
+    /// `InsertAuthoredLeaf`, instead of `H(data)`.
+    pub bind_author: bool,
+    pub hasher: LeafHasher,
+}
+
+impl LeafBuffer {
+    pub const LEN: usize = size_of::<Pubkey>()
+        + size_of::<u64>()
+        + size_of::<u8>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<bool>()
+        + LeafHasher::LEN;
+
+    pub fn new(authority: Pubkey, nonce: u64, bump: u8, data_len: u32, bind_author: bool) -> Self {
+        let mut hasher = LeafHasher::new();
+        if bind_author {
+            hasher.update(authority.as_ref());
+        }
+        Self {
+            authority,
+            nonce,
+            bump,
+            data_len,
+            written: 0,
+            bind_author,
+            hasher,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.written == self.data_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtree::{hash_authored_leaf, hash_leaf};
+
+    #[test]
+    fn test_borsh_buffer_size() {
+        let buffer = LeafBuffer::new(Pubkey::new_unique(), 1, 255, 10_000, true);
+        assert_eq!(buffer.try_to_vec().unwrap().len(), LeafBuffer::LEN);
+    }
+
+    #[test]
+    fn test_leaf_hasher() {
+        let data = (0..1000u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        for len in [0, 1, 55, 56, 63, 64, 65, 119, 120, 128, 1000] {
+            for chunk_size in [1, 13, 64, 100, 1000] {
+                let mut hasher = LeafHasher::new();
+                for chunk in data[..len].chunks(chunk_size) {
+                    hasher.update(chunk);
+                }
+                assert_eq!(hasher.finalize(), hash_leaf(&data[..len]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bind_author() {
+        let authority = Pubkey::new_unique();
+        let mut buffer = LeafBuffer::new(authority, 0, 0, 4, true);
+        buffer.hasher.update(b"leaf");
+        assert_eq!(
+            buffer.hasher.finalize(),
+            hash_authored_leaf(&authority, b"leaf")
+        );
+    }
+}
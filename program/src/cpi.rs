@@ -0,0 +1,95 @@
+use crate::instruction::MTreeInstruction;
+use crate::return_data::InsertLeafReturn;
+use borsh::BorshSerialize as _;
+use solana_program::{
+    account_info::AccountInfo,
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+    program_error::ProgramError,
+};
+
+/// Accounts of an `InsertLeaf` call made from another program.
+pub struct InsertLeafAccounts<'a, 'info> {
+    pub payer: &'a AccountInfo<'info>,
+    pub author: &'a AccountInfo<'info>,
+    pub info: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    /// Sub tree accounts from the current sub tree up to the root, followed
+    /// by the leaf marker account if the tree has `dedup` enabled.
+    pub sub_trees: &'a [AccountInfo<'info>],
+}
+
+impl<'info> InsertLeafAccounts<'_, 'info> {
+    fn to_account_metas(&self) -> Vec<AccountMeta> {
+        let mut accounts = Vec::with_capacity(4 + self.sub_trees.len());
+        accounts.push(AccountMeta::new(*self.payer.key, true));
+        accounts.push(AccountMeta::new_readonly(*self.author.key, true));
+        accounts.push(AccountMeta::new(*self.info.key, false));
+        accounts.push(AccountMeta::new_readonly(*self.system_program.key, false));
+        for sub_tree in self.sub_trees {
+            accounts.push(AccountMeta::new(*sub_tree.key, false));
+        }
+        accounts
+    }
+
+    fn to_account_infos(&self) -> Vec<AccountInfo<'info>> {
+        let mut accounts = Vec::with_capacity(4 + self.sub_trees.len());
+        accounts.push(self.payer.clone());
+        accounts.push(self.author.clone());
+        accounts.push(self.info.clone());
+        accounts.push(self.system_program.clone());
+        accounts.extend(self.sub_trees.iter().cloned());
+        accounts
+    }
+}
+
+/// Inserts a leaf through CPI. `signer_seeds` are used to sign for a program
+/// derived `author`; pass an empty slice if the author is a regular signer of
+/// the transaction.
+pub fn insert_leaf<'info>(
+    program: &AccountInfo<'info>,
+    accounts: InsertLeafAccounts<'_, 'info>,
+    data: Vec<u8>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<InsertLeafReturn, ProgramError> {
+    invoke_insert(
+        program,
+        accounts,
+        MTreeInstruction::InsertLeaf(data),
+        signer_seeds,
+    )
+}
+
+/// Inserts a leaf bound to its author through CPI, see [`insert_leaf`].
+pub fn insert_authored_leaf<'info>(
+    program: &AccountInfo<'info>,
+    accounts: InsertLeafAccounts<'_, 'info>,
+    data: Vec<u8>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<InsertLeafReturn, ProgramError> {
+    invoke_insert(
+        program,
+        accounts,
+        MTreeInstruction::InsertAuthoredLeaf(data),
+        signer_seeds,
+    )
+}
+
+fn invoke_insert<'info>(
+    program: &AccountInfo<'info>,
+    accounts: InsertLeafAccounts<'_, 'info>,
+    instruction: MTreeInstruction,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<InsertLeafReturn, ProgramError> {
+    let instruction = Instruction {
+        program_id: *program.key,
+        accounts: accounts.to_account_metas(),
+        data: instruction.try_to_vec()?,
+    };
+
+    let mut account_infos = accounts.to_account_infos();
+    account_infos.push(program.clone());
+    invoke_signed(&instruction, &account_infos, signer_seeds)?;
+
+    InsertLeafReturn::from_cpi(program.key).ok_or(ProgramError::InvalidAccountData)
+}
@@ -1,7 +1,10 @@
 use crate::{error::MtreeError, instruction::MTreeInstruction, processor};
 use borsh::BorshDeserialize as _;
 use solana_program::entrypoint;
-use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, msg, pubkey::Pubkey};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::PrintProgramError,
+    pubkey::Pubkey,
+};
 
 entrypoint!(process_instruction);
 
@@ -9,6 +12,15 @@ fn process_instruction<'a>(
     program_id: &'a Pubkey,
     accounts: &'a [AccountInfo<'a>],
     instruction_data: &[u8],
+) -> ProgramResult {
+    // Logs program errors by name rather than as a bare custom code.
+    process(program_id, accounts, instruction_data).inspect_err(|err| err.print::<MtreeError>())
+}
+
+fn process<'a>(
+    program_id: &'a Pubkey,
+    accounts: &'a [AccountInfo<'a>],
+    instruction_data: &[u8],
 ) -> ProgramResult {
     let instruction = MTreeInstruction::try_from_slice(instruction_data)
         .map_err(|_| MtreeError::InvalidInstruction)?;
@@ -17,5 +29,77 @@ fn process_instruction<'a>(
             msg!("Instruction: InsertLeaf");
             processor::insert_leaf(program_id, accounts, leaf)
         }
+        MTreeInstruction::InsertAuthoredLeaf(leaf) => {
+            msg!("Instruction: InsertAuthoredLeaf");
+            processor::insert_authored_leaf(program_id, accounts, leaf)
+        }
+        MTreeInstruction::InsertSignedLeaf { nonce, leaf } => {
+            msg!("Instruction: InsertSignedLeaf");
+            processor::insert_signed_leaf(program_id, accounts, nonce, leaf)
+        }
+        MTreeInstruction::InitTree(config) => {
+            msg!("Instruction: InitTree");
+            processor::init_tree(program_id, accounts, config)
+        }
+        MTreeInstruction::InitBuffer {
+            nonce,
+            data_len,
+            bind_author,
+        } => {
+            msg!("Instruction: InitBuffer");
+            processor::init_buffer(program_id, accounts, nonce, data_len, bind_author)
+        }
+        MTreeInstruction::WriteBuffer { offset, data } => {
+            msg!("Instruction: WriteBuffer");
+            processor::write_buffer(program_id, accounts, offset, data)
+        }
+        MTreeInstruction::InsertFromBuffer => {
+            msg!("Instruction: InsertFromBuffer");
+            processor::insert_from_buffer(program_id, accounts)
+        }
+        MTreeInstruction::InitIndexedTree => {
+            msg!("Instruction: InitIndexedTree");
+            processor::init_indexed_tree(program_id, accounts)
+        }
+        MTreeInstruction::InsertIndexed {
+            value,
+            low_leaf_index,
+            low_leaf_proof,
+        } => {
+            msg!("Instruction: InsertIndexed");
+            processor::insert_indexed(program_id, accounts, value, low_leaf_index, low_leaf_proof)
+        }
+        MTreeInstruction::InitSparseTree => {
+            msg!("Instruction: InitSparseTree");
+            processor::init_sparse_tree(program_id, accounts)
+        }
+        MTreeInstruction::SetSparseValue { key, value, proof } => {
+            msg!("Instruction: SetSparseValue");
+            processor::set_sparse_value(program_id, accounts, key, value, proof)
+        }
+        MTreeInstruction::DeleteSparseValue { key, slot, proof } => {
+            msg!("Instruction: DeleteSparseValue");
+            processor::delete_sparse_value(program_id, accounts, key, slot, proof)
+        }
+        MTreeInstruction::AppendMmrLeaf(leaf) => {
+            msg!("Instruction: AppendMmrLeaf");
+            processor::append_mmr_leaf(program_id, accounts, leaf)
+        }
+        MTreeInstruction::VerifyLeaves {
+            leaf_indexes,
+            leaves,
+            proof,
+        } => {
+            msg!("Instruction: VerifyLeaves");
+            processor::verify_leaves(program_id, accounts, leaf_indexes, leaves, proof)
+        }
+        MTreeInstruction::CloseBuffer => {
+            msg!("Instruction: CloseBuffer");
+            processor::close_buffer(program_id, accounts)
+        }
+        MTreeInstruction::MigrateInfo => {
+            msg!("Instruction: MigrateInfo");
+            processor::migrate_info(program_id, accounts)
+        }
     }
 }
@@ -1,3 +1,5 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive as _;
 use solana_program::{
     decode_error::DecodeError,
     msg,
@@ -5,34 +7,147 @@ use solana_program::{
 };
 use thiserror::Error;
 
-#[derive(Error, Clone, Debug, Eq, PartialEq)]
+/// Errors of the program. The discriminants are the on-chain error codes:
+/// never reuse or change them, only append new variants.
+#[derive(Error, Clone, Debug, Eq, PartialEq, FromPrimitive)]
 pub enum MtreeError {
     #[error("Invalid instruction")]
-    InvalidInstruction,
+    InvalidInstruction = 0,
 
     #[error("Expected signer account")]
-    ExpectedSignerAccount,
+    ExpectedSignerAccount = 1,
 
     #[error("Invalid info account")]
-    InvalidInfoAccount,
+    InvalidInfoAccount = 2,
 
     #[error("Expected writable account")]
-    ExpectedWritableAccount,
+    ExpectedWritableAccount = 3,
 
     #[error("Invalid system program")]
-    InvalidSystemProgram,
+    InvalidSystemProgram = 4,
 
     #[error("Invalid node account")]
-    InvalidNodeAccount,
+    InvalidNodeAccount = 5,
 
     #[error("Invalid sub tree account")]
-    SubTreeFull,
+    SubTreeFull = 6,
 
     #[error("Uninitialized sub tree account")]
-    UninitializedSubTree,
+    UninitializedSubTree = 7,
 
     #[error("Failed to send event")]
-    FailedToSendEvent,
+    FailedToSendEvent = 8,
+
+    #[error("Tree is already initialized")]
+    TreeAlreadyInitialized = 9,
+
+    #[error("Uninitialized tree")]
+    UninitializedTree = 10,
+
+    #[error("Leaf signer is not configured")]
+    LeafSignerNotConfigured = 11,
+
+    #[error("Expected Ed25519 signature of the leaf")]
+    MissingLeafSignature = 12,
+
+    #[error("Invalid leaf signature")]
+    InvalidLeafSignature = 13,
+
+    #[error("Invalid buffer account")]
+    InvalidBufferAccount = 14,
+
+    #[error("Buffer is already initialized")]
+    BufferAlreadyInitialized = 15,
+
+    #[error("Uninitialized buffer")]
+    UninitializedBuffer = 16,
+
+    #[error("Invalid buffer authority")]
+    InvalidBufferAuthority = 17,
+
+    #[error("Buffer write is out of bounds")]
+    BufferWriteOutOfBounds = 18,
+
+    #[error("Invalid leaf policy")]
+    InvalidLeafPolicy = 19,
+
+    #[error("Empty leaf")]
+    EmptyLeaf = 20,
+
+    #[error("Leaf is shorter than the tree allows")]
+    LeafTooShort = 21,
+
+    #[error("Leaf is longer than the tree allows")]
+    LeafTooLong = 22,
+
+    #[error("Leaf length differs from the fixed leaf length of the tree")]
+    InvalidLeafLength = 23,
+
+    #[error("Leaf is already in the tree")]
+    DuplicateLeaf = 24,
+
+    #[error("Invalid leaf marker account")]
+    InvalidLeafMarkerAccount = 25,
+
+    #[error("Indexed tree is full")]
+    IndexedTreeFull = 26,
+
+    #[error("Value does not follow the low leaf")]
+    InvalidLowLeaf = 27,
+
+    #[error("Invalid low leaf proof")]
+    InvalidLowLeafProof = 28,
+
+    #[error("Invalid indexed leaf account")]
+    InvalidIndexedLeafAccount = 29,
+
+    #[error("Invalid tree authority")]
+    InvalidTreeAuthority = 30,
+
+    #[error("Invalid sparse entry account")]
+    InvalidSparseEntryAccount = 31,
+
+    #[error("Invalid sparse proof")]
+    InvalidSparseProof = 32,
+
+    #[error("Merkle mountain range is full")]
+    MmrFull = 33,
+
+    #[error("Invalid proof")]
+    InvalidProof = 34,
+
+    #[error("Buffer chunks must be written in order")]
+    BufferWriteOutOfOrder = 35,
+
+    #[error("Buffer is not fully written")]
+    IncompleteBuffer = 36,
+
+    #[error("Tree requires the buffer leaf to be bound to its author")]
+    BufferNotAuthorBound = 37,
+
+    #[error("Invalid MMR page account")]
+    InvalidMmrPageAccount = 38,
+
+    #[error("Invalid indexed tree account")]
+    InvalidIndexedTreeAccount = 39,
+
+    #[error("Invalid sparse slot account")]
+    InvalidSparseSlotAccount = 40,
+
+    #[error("Info account must be migrated with MigrateInfo")]
+    InfoNotMigrated = 41,
+
+    #[error("Info account is already migrated")]
+    InfoAlreadyMigrated = 42,
+
+    #[error("Invalid program data account")]
+    InvalidProgramDataAccount = 43,
+
+    #[error("Invalid signed leaf marker account")]
+    InvalidSignedLeafMarkerAccount = 44,
+
+    #[error("Signed leaf message was already inserted")]
+    SignedLeafReplayed = 45,
 }
 
 impl PrintProgramError for MtreeError {
@@ -47,8 +162,99 @@ impl From<MtreeError> for ProgramError {
     }
 }
 
-impl<T> DecodeError<T> for MtreeError {
+impl TryFrom<u32> for MtreeError {
+    type Error = u32;
+
+    /// Decodes the code of `ProgramError::Custom`, returning unknown codes
+    /// back.
+    fn try_from(code: u32) -> Result<Self, u32> {
+        Self::from_u32(code).ok_or(code)
+    }
+}
+
+impl DecodeError<MtreeError> for MtreeError {
     fn type_of() -> &'static str {
-        "Mpl Project Name Error"
+        "MtreeError"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [(MtreeError, u32); 46] = [
+        (MtreeError::InvalidInstruction, 0),
+        (MtreeError::ExpectedSignerAccount, 1),
+        (MtreeError::InvalidInfoAccount, 2),
+        (MtreeError::ExpectedWritableAccount, 3),
+        (MtreeError::InvalidSystemProgram, 4),
+        (MtreeError::InvalidNodeAccount, 5),
+        (MtreeError::SubTreeFull, 6),
+        (MtreeError::UninitializedSubTree, 7),
+        (MtreeError::FailedToSendEvent, 8),
+        (MtreeError::TreeAlreadyInitialized, 9),
+        (MtreeError::UninitializedTree, 10),
+        (MtreeError::LeafSignerNotConfigured, 11),
+        (MtreeError::MissingLeafSignature, 12),
+        (MtreeError::InvalidLeafSignature, 13),
+        (MtreeError::InvalidBufferAccount, 14),
+        (MtreeError::BufferAlreadyInitialized, 15),
+        (MtreeError::UninitializedBuffer, 16),
+        (MtreeError::InvalidBufferAuthority, 17),
+        (MtreeError::BufferWriteOutOfBounds, 18),
+        (MtreeError::InvalidLeafPolicy, 19),
+        (MtreeError::EmptyLeaf, 20),
+        (MtreeError::LeafTooShort, 21),
+        (MtreeError::LeafTooLong, 22),
+        (MtreeError::InvalidLeafLength, 23),
+        (MtreeError::DuplicateLeaf, 24),
+        (MtreeError::InvalidLeafMarkerAccount, 25),
+        (MtreeError::IndexedTreeFull, 26),
+        (MtreeError::InvalidLowLeaf, 27),
+        (MtreeError::InvalidLowLeafProof, 28),
+        (MtreeError::InvalidIndexedLeafAccount, 29),
+        (MtreeError::InvalidTreeAuthority, 30),
+        (MtreeError::InvalidSparseEntryAccount, 31),
+        (MtreeError::InvalidSparseProof, 32),
+        (MtreeError::MmrFull, 33),
+        (MtreeError::InvalidProof, 34),
+        (MtreeError::BufferWriteOutOfOrder, 35),
+        (MtreeError::IncompleteBuffer, 36),
+        (MtreeError::BufferNotAuthorBound, 37),
+        (MtreeError::InvalidMmrPageAccount, 38),
+        (MtreeError::InvalidIndexedTreeAccount, 39),
+        (MtreeError::InvalidSparseSlotAccount, 40),
+        (MtreeError::InfoNotMigrated, 41),
+        (MtreeError::InfoAlreadyMigrated, 42),
+        (MtreeError::InvalidProgramDataAccount, 43),
+        (MtreeError::InvalidSignedLeafMarkerAccount, 44),
+        (MtreeError::SignedLeafReplayed, 45),
+    ];
+
+    #[test]
+    fn test_error_codes() {
+        for (err, code) in CODES {
+            assert_eq!(ProgramError::from(err.clone()), ProgramError::Custom(code));
+            assert_eq!(MtreeError::try_from(code), Ok(err));
+        }
+        assert_eq!(
+            MtreeError::try_from(CODES.len() as u32),
+            Err(CODES.len() as u32)
+        );
+    }
+
+    #[test]
+    fn test_decode_error() {
+        for (err, code) in CODES {
+            assert_eq!(MtreeError::decode_custom_error_to_enum(code), Some(err));
+        }
+        assert_eq!(
+            MtreeError::decode_custom_error_to_enum(CODES.len() as u32),
+            None
+        );
+        assert_eq!(
+            <MtreeError as DecodeError<MtreeError>>::type_of(),
+            "MtreeError"
+        );
     }
 }
@@ -6,6 +6,12 @@ use solana_program::msg;
 #[derive(BorshDeserialize, BorshSerialize, Debug)]
 pub enum MTreeEvent {
     NewRootHash(Hash),
+    /// A leaf appended to the Merkle mountain range. Its nodes never move, so
+    /// these events are enough to rebuild proofs.
+    MmrLeafAppended {
+        leaf_index: u64,
+        leaf_hash: Hash,
+    },
 }
 
 impl MTreeEvent {
@@ -1,26 +1,273 @@
-use crate::mtree::{Hash, SubTreeId};
+use crate::error::MtreeError;
+use crate::mtree::{
+    mmr::Mmr, path::get_leaf_position, proof::MultiProofLeaf, sparse::SparseKey, Hash, SubTreeId,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 use core::mem::size_of;
-use solana_program::pubkey::Pubkey;
+use solana_program::{bpf_loader_upgradeable, hash::hashv, pubkey::Pubkey};
 
 pub fn find_sub_tree_pda(node_id: SubTreeId, program_id: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(&[&node_id.to_be_bytes()[..]], program_id)
 }
 
+/// Program data account of the program, deployed with the upgradeable
+/// loader.
+pub fn find_program_data_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::ID)
+}
+
 pub const INFO_SEED: &[u8] = b"info";
 
 pub fn find_info_pda(program_id: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(&[INFO_SEED], program_id)
 }
 
+pub const INDEXED_TREE_SEED: &[u8] = b"indexed_tree";
+
+pub fn find_indexed_tree_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INDEXED_TREE_SEED], program_id)
+}
+
+pub const INDEXED_LEAF_SEED: &[u8] = b"indexed_leaf";
+
+pub fn find_indexed_leaf_pda(index: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INDEXED_LEAF_SEED, &index.to_be_bytes()], program_id)
+}
+
+pub const SPARSE_TREE_SEED: &[u8] = b"sparse_tree";
+
+pub fn find_sparse_tree_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SPARSE_TREE_SEED], program_id)
+}
+
+pub const SPARSE_ENTRY_SEED: &[u8] = b"sparse_entry";
+
+pub fn find_sparse_entry_pda(key: &SparseKey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SPARSE_ENTRY_SEED, key], program_id)
+}
+
+pub const SPARSE_SLOT_SEED: &[u8] = b"sparse_slot";
+
+pub fn find_sparse_slot_pda(slot: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SPARSE_SLOT_SEED, &slot.to_be_bytes()], program_id)
+}
+
+pub const MMR_PAGE_SEED: &[u8] = b"mmr_page";
+
+pub fn find_mmr_page_pda(page: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MMR_PAGE_SEED, &page.to_be_bytes()], program_id)
+}
+
+pub const BUFFER_SEED: &[u8] = b"buffer";
+
+pub fn find_buffer_pda(authority: &Pubkey, nonce: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[BUFFER_SEED, authority.as_ref(), &nonce.to_be_bytes()],
+        program_id,
+    )
+}
+
+pub const LEAF_MARKER_SEED: &[u8] = b"leaf";
+
+pub fn find_leaf_marker_pda(leaf_hash: &Hash, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LEAF_MARKER_SEED, leaf_hash], program_id)
+}
+
+pub const SIGNED_LEAF_SEED: &[u8] = b"signed_leaf";
+
+/// Returns the replay marker PDA of a signed leaf message, see
+/// `signed_leaf_message_hash`.
+pub fn find_signed_leaf_pda(message_hash: &Hash, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SIGNED_LEAF_SEED, message_hash], program_id)
+}
+
+/// How leaf data is turned into the leaf hash.
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, Debug, Default, Eq, PartialEq)]
+pub enum LeafMode {
+    /// `H(data)`, unless the leaf is inserted with `InsertAuthoredLeaf`.
+    #[default]
+    Plain,
+    /// `H(author || data)` for every leaf, so each leaf proves who signed it.
+    AuthorBound,
+}
+
+/// Limits on the length of the leaf data. The default policy accepts any
+/// leaf.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, Eq, PartialEq)]
+pub struct LeafPolicy {
+    /// Minimum leaf length in bytes.
+    pub min_len: u32,
+    /// Maximum leaf length in bytes, `0` for no limit.
+    pub max_len: u32,
+    /// Rejects empty leaves, also when `min_len` is `0`.
+    pub reject_empty: bool,
+    /// Length every leaf must have, `0` disables the fixed size mode.
+    pub fixed_len: u32,
+}
+
+impl LeafPolicy {
+    pub const LEN: usize = 3 * size_of::<u32>() + size_of::<bool>();
+
+    /// Checks that at least one leaf length satisfies the policy.
+    pub fn validate(&self) -> Result<(), MtreeError> {
+        let max_len = if self.max_len == 0 {
+            u32::MAX
+        } else {
+            self.max_len
+        };
+        let conflicting_fixed_len =
+            self.fixed_len != 0 && (self.fixed_len < self.min_len || self.fixed_len > max_len);
+
+        if self.min_len > max_len || conflicting_fixed_len {
+            return Err(MtreeError::InvalidLeafPolicy);
+        }
+        Ok(())
+    }
+
+    /// Checks the length of a leaf against the policy.
+    pub fn check(&self, len: usize) -> Result<(), MtreeError> {
+        if self.reject_empty && len == 0 {
+            return Err(MtreeError::EmptyLeaf);
+        }
+        if self.fixed_len != 0 && len != self.fixed_len as usize {
+            return Err(MtreeError::InvalidLeafLength);
+        }
+        if len < self.min_len as usize {
+            return Err(MtreeError::LeafTooShort);
+        }
+        if self.max_len != 0 && len > self.max_len as usize {
+            return Err(MtreeError::LeafTooLong);
+        }
+        Ok(())
+    }
+}
+
+/// Settings chosen when the tree is created. They cannot be changed later.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, Eq, PartialEq)]
+pub struct TreeConfig {
+    pub leaf_mode: LeafMode,
+    /// Off-chain signer whose Ed25519 signature `InsertSignedLeaf` requires.
+    /// `Pubkey::default()` disables signed inserts.
+    pub leaf_signer: Pubkey,
+    pub leaf_policy: LeafPolicy,
+    /// Rejects leaves whose hash is already in the tree. Every leaf gets a
+    /// `LeafMarker` account.
+    pub dedup: bool,
+}
+
+impl TreeConfig {
+    pub const LEN: usize =
+        size_of::<u8>() + size_of::<Pubkey>() + LeafPolicy::LEN + size_of::<bool>();
+}
+
+/// Account created for every leaf of a tree with `dedup` enabled, at the PDA
+/// derived from the leaf hash.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, Eq, PartialEq)]
+pub struct LeafMarker {
+    pub leaf_index: u64,
+    /// Slot in which the leaf was inserted.
+    pub slot: u64,
+}
+
+impl LeafMarker {
+    pub const LEN: usize = size_of::<u64>() + size_of::<u64>();
+}
+
+/// Prefix of the messages signed for `InsertSignedLeaf`.
+pub const SIGNED_LEAF_DOMAIN: &[u8] = b"solana-mtree:signed-leaf";
+
+/// Returns the message the `leaf_signer` signs to approve `leaf`:
+/// `SIGNED_LEAF_DOMAIN || info || nonce || leaf`. The info account ties the
+/// signature to one deployment. The nonce is picked by the signer and only
+/// has to differ between approvals of the same leaf.
+pub fn signed_leaf_message(info: &Pubkey, nonce: u64, leaf: &[u8]) -> Vec<u8> {
+    let mut message =
+        Vec::with_capacity(SIGNED_LEAF_DOMAIN.len() + size_of::<Pubkey>() + 8 + leaf.len());
+    message.extend_from_slice(SIGNED_LEAF_DOMAIN);
+    message.extend_from_slice(info.as_ref());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.extend_from_slice(leaf);
+    message
+}
+
+/// Hash seeding the `SignedLeafMarker` of a signed leaf message.
+pub fn signed_leaf_message_hash(message: &[u8]) -> Hash {
+    hashv(&[message]).to_bytes()
+}
+
+/// Account created for every `InsertSignedLeaf`, at the PDA derived from the
+/// hash of the signed message, so each approval is accepted only once.
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default, Eq, PartialEq)]
+pub struct SignedLeafMarker {
+    /// Slot in which the leaf was inserted.
+    pub slot: u64,
+}
+
+impl SignedLeafMarker {
+    pub const LEN: usize = size_of::<u64>();
+}
+
+/// Version of the `MTreeInfo` layout written by this program.
+pub const INFO_VERSION: u8 = 1;
+
+/// Info account layout before `MTreeInfo` was versioned. `MigrateInfo`
+/// converts such accounts to the current layout.
 #[derive(Clone, BorshSerialize, BorshDeserialize, Debug, Default)]
+pub struct LegacyMTreeInfo {
+    pub tree_id: SubTreeId,
+    pub root_hash: Hash,
+}
+
+impl LegacyMTreeInfo {
+    pub const LEN: usize = size_of::<SubTreeId>() + size_of::<Hash>();
+}
+
+#[derive(Clone, BorshSerialize, BorshDeserialize, Debug)]
 pub struct MTreeInfo {
     pub tree_id: SubTreeId,
     pub root_hash: Hash,
+    /// Layout version, follows the fields of `LegacyMTreeInfo` so both
+    /// layouts share their prefix.
+    pub version: u8,
+    pub leaf_count: u64,
+    pub config: TreeConfig,
+    /// Merkle mountain range of the leaves appended with `AppendMmrLeaf`.
+    pub mmr: Mmr,
+}
+
+impl Default for MTreeInfo {
+    fn default() -> Self {
+        Self {
+            tree_id: 0,
+            root_hash: Hash::default(),
+            version: INFO_VERSION,
+            leaf_count: 0,
+            config: TreeConfig::default(),
+            mmr: Mmr::default(),
+        }
+    }
 }
 
 impl MTreeInfo {
-    pub const LEN: usize = size_of::<SubTreeId>() + size_of::<Hash>();
+    pub const LEN: usize =
+        LegacyMTreeInfo::LEN + size_of::<u8>() + size_of::<u64>() + TreeConfig::LEN + Mmr::LEN;
+
+    /// Returns the current location `(sub tree id, leaf position)` of the leaf
+    /// with the given global index, or `None` if it was not inserted yet.
+    pub fn leaf_position(&self, leaf_index: u64) -> Option<(SubTreeId, usize)> {
+        if leaf_index < self.leaf_count {
+            Some(get_leaf_position(leaf_index, self.leaf_count))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the place in a `MerkleMultiProof` against the current root of
+    /// the leaf with the given global index.
+    pub fn multi_proof_leaf(&self, leaf_index: u64) -> Option<MultiProofLeaf> {
+        let (id, position) = self.leaf_position(leaf_index)?;
+        MultiProofLeaf::from_sub_tree_position(id, position)
+    }
 }
 
 #[cfg(test)]
@@ -32,5 +279,123 @@ mod tests {
         let info = MTreeInfo::default();
         let size = info.try_to_vec().unwrap().len();
         assert_eq!(size, MTreeInfo::LEN);
+
+        let info = MTreeInfo {
+            config: TreeConfig {
+                leaf_mode: LeafMode::AuthorBound,
+                leaf_signer: Pubkey::new_unique(),
+                leaf_policy: LeafPolicy {
+                    min_len: 1,
+                    max_len: 1024,
+                    reject_empty: true,
+                    fixed_len: 32,
+                },
+                dedup: true,
+            },
+            ..Default::default()
+        };
+        assert_eq!(info.try_to_vec().unwrap().len(), MTreeInfo::LEN);
+    }
+
+    #[test]
+    fn test_info_extends_legacy_layout() {
+        let info = MTreeInfo {
+            tree_id: 3,
+            root_hash: [7; 32],
+            ..Default::default()
+        };
+        let data = info.try_to_vec().unwrap();
+        let legacy = LegacyMTreeInfo::try_from_slice(&data[..LegacyMTreeInfo::LEN]).unwrap();
+        assert_eq!(legacy.tree_id, 3);
+        assert_eq!(legacy.root_hash, [7; 32]);
+        assert_eq!(data[LegacyMTreeInfo::LEN], INFO_VERSION);
+    }
+
+    #[test]
+    fn test_borsh_leaf_marker_size() {
+        let marker = LeafMarker {
+            leaf_index: 1,
+            slot: 2,
+        };
+        assert_eq!(marker.try_to_vec().unwrap().len(), LeafMarker::LEN);
+    }
+
+    #[test]
+    fn test_leaf_policy() {
+        let policy = LeafPolicy::default();
+        assert_eq!(policy.validate(), Ok(()));
+        assert_eq!(policy.check(0), Ok(()));
+
+        let policy = LeafPolicy {
+            min_len: 2,
+            max_len: 4,
+            ..Default::default()
+        };
+        assert_eq!(policy.check(1), Err(MtreeError::LeafTooShort));
+        assert_eq!(policy.check(4), Ok(()));
+        assert_eq!(policy.check(5), Err(MtreeError::LeafTooLong));
+
+        let policy = LeafPolicy {
+            reject_empty: true,
+            ..Default::default()
+        };
+        assert_eq!(policy.check(0), Err(MtreeError::EmptyLeaf));
+
+        let policy = LeafPolicy {
+            fixed_len: 32,
+            ..Default::default()
+        };
+        assert_eq!(policy.check(32), Ok(()));
+        assert_eq!(policy.check(31), Err(MtreeError::InvalidLeafLength));
+
+        let conflicting = [
+            LeafPolicy {
+                min_len: 5,
+                max_len: 4,
+                ..Default::default()
+            },
+            LeafPolicy {
+                max_len: 16,
+                fixed_len: 32,
+                ..Default::default()
+            },
+        ];
+        for policy in conflicting {
+            assert_eq!(policy.validate(), Err(MtreeError::InvalidLeafPolicy));
+        }
+    }
+
+    #[test]
+    fn test_borsh_signed_leaf_marker_size() {
+        let marker = SignedLeafMarker { slot: u64::MAX };
+        assert_eq!(marker.try_to_vec().unwrap().len(), SignedLeafMarker::LEN);
+    }
+
+    #[test]
+    fn test_signed_leaf_message() {
+        let info = Pubkey::new_unique();
+        let message = signed_leaf_message(&info, 7, b"leaf");
+        assert!(message.starts_with(SIGNED_LEAF_DOMAIN));
+        assert_eq!(
+            message[SIGNED_LEAF_DOMAIN.len()..],
+            [info.as_ref(), &7u64.to_le_bytes(), b"leaf"].concat()
+        );
+        assert_ne!(message, signed_leaf_message(&info, 8, b"leaf"));
+        assert_ne!(
+            message,
+            signed_leaf_message(&Pubkey::new_unique(), 7, b"leaf")
+        );
+    }
+
+    #[test]
+    fn test_leaf_position() {
+        let info = MTreeInfo {
+            leaf_count: 8,
+            ..Default::default()
+        };
+        assert_eq!(info.leaf_position(0), Some((1, 0)));
+        assert_eq!(info.leaf_position(1), Some((0, 1)));
+        assert_eq!(info.leaf_position(7), Some((1, 1)));
+        assert_eq!(info.leaf_position(8), None);
     }
 }
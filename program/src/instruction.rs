@@ -1,34 +1,293 @@
+use crate::info::TreeConfig;
+use crate::mtree::{
+    proof::MerkleMultiProof,
+    sparse::{SparseKey, SparseProof},
+    Hash,
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 
 #[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub enum MTreeInstruction {
+    /// Appends a leaf to the tree.
+    ///
+    /// Breaking change: the single `sender` account of the original layout
+    /// is split into a payer and an author, and the author must sign. A
+    /// caller of the original layout passes its sender as both.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds rent and the insert commission
+    /// 1. `[signer]` author, may be the payer or a PDA signing through CPI
+    /// 2. `[writable]` info account
+    /// 3. `[]` system program
+    /// 4. `[writable]` sub tree accounts from the current sub tree up to the
+    ///    root, in any order, optionally with those of the next sub tree
+    /// 5. `[writable]` leaf marker account, if the tree has `dedup` enabled
+    /// 6. `[]` rent sysvar, unused and optional, appended by the
+    ///    `make_insert_*` builders as in the original layout
     InsertLeaf(Vec<u8>),
+
+    /// Appends a leaf bound to its author: the leaf hash is
+    /// `H(author || data)`.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds rent and the insert commission
+    /// 1. `[signer]` author, may be the payer or a PDA signing through CPI
+    /// 2. `[writable]` info account
+    /// 3. `[]` system program
+    /// 4. `[writable]` sub tree accounts from the current sub tree up to the
+    ///    root, in any order, optionally with those of the next sub tree
+    /// 5. `[writable]` leaf marker account, if the tree has `dedup` enabled
+    /// 6. `[]` rent sysvar, unused and optional
+    InsertAuthoredLeaf(Vec<u8>),
+
+    /// Creates the tree with the given config. Only the upgrade authority of
+    /// the program can choose the config, trees created implicitly by the
+    /// first insert use the default config.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer
+    /// 1. `[writable]` info account
+    /// 2. `[]` system program
+    /// 3. `[signer]` upgrade authority of the program
+    /// 4. `[]` program data account of the program
+    InitTree(TreeConfig),
+
+    /// Appends a leaf signed by the `leaf_signer` of the tree. The previous
+    /// instruction of the transaction must be an Ed25519 precompile
+    /// instruction verifying the signature of
+    /// `info::signed_leaf_message(info, nonce, leaf)`. Every insert creates
+    /// the replay marker of the message, so a signature approves exactly one
+    /// insert, while inserts signed with different nonces stay independent.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds rent and the insert commission
+    /// 1. `[signer]` author
+    /// 2. `[writable]` info account
+    /// 3. `[]` system program
+    /// 4. `[]` instructions sysvar
+    /// 5. `[writable]` replay marker account of the message
+    /// 6. `[writable]` sub tree accounts from the current sub tree up to the
+    ///    root, in any order, optionally with those of the next sub tree
+    /// 7. `[writable]` leaf marker account, if the tree has `dedup` enabled
+    /// 8. `[]` rent sysvar, unused and optional
+    InsertSignedLeaf { nonce: u64, leaf: Vec<u8> },
+
+    /// Creates a buffer staging a leaf of `data_len` bytes, at the PDA of the
+    /// authority and `nonce`. With `bind_author` the leaf hash is
+    /// `H(authority || data)`, which author-bound trees require.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds the buffer account
+    /// 1. `[signer]` buffer authority
+    /// 2. `[writable]` buffer account
+    /// 3. `[]` system program
+    InitBuffer {
+        nonce: u64,
+        data_len: u32,
+        bind_author: bool,
+    },
+
+    /// Hashes the next chunk of the leaf into the buffer. `offset` must be
+    /// the number of bytes written so far.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` buffer authority
+    /// 1. `[writable]` buffer account
+    WriteBuffer { offset: u32, data: Vec<u8> },
+
+    /// Appends the leaf staged in the buffer, once fully written, and closes
+    /// the buffer, its lamports go to the payer. The author must be the
+    /// buffer authority.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds rent and the insert commission
+    /// 1. `[signer]` author
+    /// 2. `[writable]` info account
+    /// 3. `[]` system program
+    /// 4. `[writable]` buffer account
+    /// 5. `[writable]` sub tree accounts from the current sub tree up to the
+    ///    root, in any order, optionally with those of the next sub tree
+    /// 6. `[writable]` leaf marker account, if the tree has `dedup` enabled
+    /// 7. `[]` rent sysvar, unused and optional
+    InsertFromBuffer,
+
+    /// Creates the indexed tree, holding the zero leaf.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer
+    /// 1. `[writable]` indexed tree account
+    /// 2. `[writable]` indexed leaf account 0
+    /// 3. `[]` system program
+    InitIndexedTree,
+
+    /// Inserts a value into the indexed tree after its low leaf, the leaf
+    /// with the largest smaller value. The proof of the low leaf is checked
+    /// against the current root.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer
+    /// 1. `[writable]` indexed tree account
+    /// 2. `[writable]` low leaf account
+    /// 3. `[writable]` new leaf account, at the next index of the tree
+    /// 4. `[]` system program
+    InsertIndexed {
+        value: Hash,
+        low_leaf_index: u64,
+        low_leaf_proof: Vec<Hash>,
+    },
+
+    /// Creates the sparse tree, whose values only the authority can change.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer
+    /// 1. `[signer]` authority
+    /// 2. `[writable]` sparse tree account
+    /// 3. `[]` system program
+    InitSparseTree,
+
+    /// Sets the value of a key in the sparse tree. The proof of the current
+    /// leaf of the key, empty if the key has no value, is checked against
+    /// the current root.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds the entry and slot accounts of a
+    ///    new key
+    /// 1. `[signer]` authority
+    /// 2. `[writable]` sparse tree account
+    /// 3. `[writable]` sparse entry account of the key
+    /// 4. `[writable]` sparse slot account of index `key_count`, listing the
+    ///    key if it is new
+    /// 5. `[]` system program
+    SetSparseValue {
+        key: SparseKey,
+        value: Hash,
+        proof: SparseProof,
+    },
+
+    /// Removes a key from the sparse tree and closes its entry account. The
+    /// key of the last slot moves into `slot`, the slot of the removed key,
+    /// and the last slot account is closed.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` authority
+    /// 1. `[writable]` sparse tree account
+    /// 2. `[writable]` sparse entry account of the key
+    /// 3. `[writable]` sparse slot account of `slot`
+    /// 4. `[writable]` sparse slot account of index `key_count - 1`
+    /// 5. `[writable]` receiver of the lamports of the closed accounts
+    DeleteSparseValue {
+        key: SparseKey,
+        slot: u64,
+        proof: SparseProof,
+    },
+
+    /// Appends a leaf to the Merkle mountain range kept in the info account.
+    /// Unlike `InsertLeaf`, the nodes of earlier leaves never move, so their
+    /// proofs stay valid against the root they were made for. The leaf
+    /// policy and leaf mode of the tree apply as for `InsertLeaf`. The leaf
+    /// hash is also stored in the MMR page account of its index, from which
+    /// clients read back the leaves.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds rent of a new page account
+    /// 1. `[signer]` author, may be the payer or a PDA signing through CPI
+    /// 2. `[writable]` info account
+    /// 3. `[]` system program
+    /// 4. `[writable]` MMR page account of the leaf index
+    AppendMmrLeaf(Vec<u8>),
+
+    /// Checks that the leaf hashes are the leaves with the given global
+    /// indexes in the tree with the current root, failing otherwise. The
+    /// place of each leaf in the proof must be the place of that leaf index
+    /// in the tree, so an inner node cannot pass for a leaf.
+    ///
+    /// Accounts:
+    /// 0. `[]` info account
+    VerifyLeaves {
+        leaf_indexes: Vec<u64>,
+        leaves: Vec<Hash>,
+        proof: MerkleMultiProof,
+    },
+
+    /// Closes a buffer without inserting it, e.g. after a failed upload.
+    ///
+    /// Accounts:
+    /// 0. `[signer]` buffer authority
+    /// 1. `[writable]` buffer account
+    /// 2. `[writable]` receiver of the buffer lamports
+    CloseBuffer,
+
+    /// Converts an info account written in the `LegacyMTreeInfo` layout,
+    /// which every other instruction rejects with `InfoNotMigrated`. The
+    /// account is reallocated to the current layout with the default config,
+    /// and its leaf count is recovered from the sub tree the next leaf goes
+    /// to. Sub trees are left as they are, `Audit` reports those written in
+    /// a layout the current program does not follow.
+    ///
+    /// Accounts:
+    /// 0. `[writable, signer]` payer, funds the rent of the larger account
+    /// 1. `[writable]` info account
+    /// 2. `[]` sub tree account of the legacy `tree_id`
+    /// 3. `[]` system program
+    MigrateInfo,
 }
 
 #[cfg(feature = "encode")]
 pub mod encode {
     use std::io;
 
-    use crate::info::{find_info_pda, find_sub_tree_pda};
+    use crate::info::{
+        find_buffer_pda, find_indexed_leaf_pda, find_indexed_tree_pda, find_info_pda,
+        find_leaf_marker_pda, find_mmr_page_pda, find_program_data_pda, find_signed_leaf_pda,
+        find_sparse_entry_pda, find_sparse_slot_pda, find_sparse_tree_pda, find_sub_tree_pda,
+        signed_leaf_message, signed_leaf_message_hash, TreeConfig,
+    };
+    use crate::mtree::mmr::MMR_PAGE_LEAVES;
     use crate::mtree::path::get_path_to_root;
-    use crate::mtree::SubTreeId;
+    use crate::mtree::proof::MerkleMultiProof;
+    use crate::mtree::sparse::{SparseKey, SparseProof};
+    use crate::mtree::{Hash, SubTreeId};
+    use crate::return_data::InsertLeafReturn;
     use borsh::BorshSerialize as _;
     use solana_program::instruction::{AccountMeta, Instruction};
     use solana_program::pubkey::Pubkey;
 
     use super::MTreeInstruction;
 
-    pub fn make_insert_leaf_instruction(
+    pub fn make_init_tree_instruction(
         program_id: Pubkey,
-        sender: Pubkey,
-        data: Vec<u8>,
-        id: SubTreeId,
+        payer: Pubkey,
+        upgrade_authority: Pubkey,
+        config: TreeConfig,
     ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(find_info_pda(&program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+                AccountMeta::new_readonly(upgrade_authority, true),
+                AccountMeta::new_readonly(find_program_data_pda(&program_id).0, false),
+            ],
+            data: MTreeInstruction::InitTree(config).try_to_vec()?,
+        })
+    }
+
+    /// Returns the accounts expected by `InsertLeaf` when the tree is at the
+    /// given sub tree. Programs calling the mtree through CPI can append them
+    /// to their own instruction accounts.
+    pub fn insert_leaf_account_metas(
+        program_id: &Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        id: SubTreeId,
+    ) -> Vec<AccountMeta> {
         let path = get_path_to_root(id);
-        let mut accounts = Vec::with_capacity(2 + path.len());
+        let mut accounts = Vec::with_capacity(5 + path.len());
 
-        accounts.push(AccountMeta::new(sender, true));
-        accounts.push(AccountMeta::new(find_info_pda(&program_id).0, false));
+        accounts.push(AccountMeta::new(payer, true));
+        accounts.push(AccountMeta::new_readonly(author, true));
+        accounts.push(AccountMeta::new(find_info_pda(program_id).0, false));
         accounts.push(AccountMeta::new_readonly(
             solana_program::system_program::ID,
             false,
@@ -36,11 +295,417 @@ pub mod encode {
 
         for node_id in path {
             accounts.push(AccountMeta::new(
-                find_sub_tree_pda(node_id, &program_id).0,
+                find_sub_tree_pda(node_id, program_id).0,
                 false,
             ));
         }
+        accounts
+    }
+
+    pub fn make_insert_leaf_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        data: Vec<u8>,
+        id: SubTreeId,
+    ) -> Result<Instruction, io::Error> {
+        make_insert_instruction(
+            program_id,
+            payer,
+            author,
+            id,
+            MTreeInstruction::InsertLeaf(data),
+        )
+    }
+
+    pub fn make_insert_authored_leaf_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        data: Vec<u8>,
+        id: SubTreeId,
+    ) -> Result<Instruction, io::Error> {
+        make_insert_instruction(
+            program_id,
+            payer,
+            author,
+            id,
+            MTreeInstruction::InsertAuthoredLeaf(data),
+        )
+    }
+
+    /// Builds the Ed25519 precompile instruction verifying `signature` of
+    /// `message` by `signer`, with all data stored inside the instruction.
+    pub fn make_ed25519_instruction(
+        signer: &Pubkey,
+        signature: &[u8; 64],
+        message: &[u8],
+    ) -> Result<Instruction, io::Error> {
+        const DATA_START: usize = 16;
+        let public_key_offset = DATA_START;
+        let signature_offset = public_key_offset + signer.as_ref().len();
+        let message_data_offset = signature_offset + signature.len();
+        let message_data_size = u16::try_from(message.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Message is too long"))?;
+
+        let mut data = Vec::with_capacity(message_data_offset + message.len());
+        data.extend_from_slice(&[1, 0]);
+        for offset in [
+            signature_offset as u16,
+            u16::MAX,
+            public_key_offset as u16,
+            u16::MAX,
+            message_data_offset as u16,
+            message_data_size,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(signature);
+        data.extend_from_slice(message);
+
+        Ok(Instruction {
+            program_id: solana_program::ed25519_program::ID,
+            accounts: vec![],
+            data,
+        })
+    }
+
+    /// Builds the Ed25519 signature check and the `InsertSignedLeaf`
+    /// instruction, which must be sent in this order in one transaction.
+    /// `signature` signs `signed_leaf_message` with the given `nonce`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn make_insert_signed_leaf_instructions(
+        program_id: Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        signer: Pubkey,
+        signature: &[u8; 64],
+        nonce: u64,
+        data: Vec<u8>,
+        id: SubTreeId,
+    ) -> Result<Vec<Instruction>, io::Error> {
+        let message = signed_leaf_message(&find_info_pda(&program_id).0, nonce, &data);
+        let ed25519_ix = make_ed25519_instruction(&signer, signature, &message)?;
+        let marker = find_signed_leaf_pda(&signed_leaf_message_hash(&message), &program_id).0;
+        let mut insert_ix = make_insert_instruction(
+            program_id,
+            payer,
+            author,
+            id,
+            MTreeInstruction::InsertSignedLeaf { nonce, leaf: data },
+        )?;
+        insert_ix.accounts.splice(
+            4..4,
+            [
+                AccountMeta::new_readonly(solana_instructions_sysvar::ID, false),
+                AccountMeta::new(marker, false),
+            ],
+        );
+        Ok(vec![ed25519_ix, insert_ix])
+    }
+
+    pub fn make_init_buffer_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        authority: Pubkey,
+        nonce: u64,
+        data_len: u32,
+        bind_author: bool,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(find_buffer_pda(&authority, nonce, &program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            ],
+            data: MTreeInstruction::InitBuffer {
+                nonce,
+                data_len,
+                bind_author,
+            }
+            .try_to_vec()?,
+        })
+    }
+
+    pub fn make_write_buffer_instruction(
+        program_id: Pubkey,
+        authority: Pubkey,
+        nonce: u64,
+        offset: u32,
+        data: Vec<u8>,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(find_buffer_pda(&authority, nonce, &program_id).0, false),
+            ],
+            data: MTreeInstruction::WriteBuffer { offset, data }.try_to_vec()?,
+        })
+    }
+
+    /// Builds `MigrateInfo` for a legacy info account whose next leaf goes to
+    /// sub tree `tree_id`.
+    pub fn make_migrate_info_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        tree_id: SubTreeId,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(find_info_pda(&program_id).0, false),
+                AccountMeta::new_readonly(find_sub_tree_pda(tree_id, &program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            ],
+            data: MTreeInstruction::MigrateInfo.try_to_vec()?,
+        })
+    }
+
+    pub fn make_close_buffer_instruction(
+        program_id: Pubkey,
+        authority: Pubkey,
+        nonce: u64,
+        receiver: Pubkey,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(find_buffer_pda(&authority, nonce, &program_id).0, false),
+                AccountMeta::new(receiver, false),
+            ],
+            data: MTreeInstruction::CloseBuffer.try_to_vec()?,
+        })
+    }
+
+    /// Builds `InsertFromBuffer` for the buffer of the author and `nonce`.
+    pub fn make_insert_from_buffer_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        nonce: u64,
+        id: SubTreeId,
+    ) -> Result<Instruction, io::Error> {
+        let buffer = find_buffer_pda(&author, nonce, &program_id).0;
+        let mut insert_ix = make_insert_instruction(
+            program_id,
+            payer,
+            author,
+            id,
+            MTreeInstruction::InsertFromBuffer,
+        )?;
+        insert_ix
+            .accounts
+            .insert(4, AccountMeta::new(buffer, false));
+        Ok(insert_ix)
+    }
+
+    pub fn make_init_indexed_tree_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(find_indexed_tree_pda(&program_id).0, false),
+                AccountMeta::new(find_indexed_leaf_pda(0, &program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            ],
+            data: MTreeInstruction::InitIndexedTree.try_to_vec()?,
+        })
+    }
+
+    /// Builds `InsertIndexed` for a tree whose next leaf index is
+    /// `next_index`.
+    pub fn make_insert_indexed_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        value: Hash,
+        low_leaf_index: u64,
+        low_leaf_proof: Vec<Hash>,
+        next_index: u64,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(find_indexed_tree_pda(&program_id).0, false),
+                AccountMeta::new(find_indexed_leaf_pda(low_leaf_index, &program_id).0, false),
+                AccountMeta::new(find_indexed_leaf_pda(next_index, &program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            ],
+            data: MTreeInstruction::InsertIndexed {
+                value,
+                low_leaf_index,
+                low_leaf_proof,
+            }
+            .try_to_vec()?,
+        })
+    }
+
+    pub fn make_init_sparse_tree_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        authority: Pubkey,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(find_sparse_tree_pda(&program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            ],
+            data: MTreeInstruction::InitSparseTree.try_to_vec()?,
+        })
+    }
+
+    /// Builds `SetSparseValue` for a tree holding `key_count` keys.
+    pub fn make_set_sparse_value_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        authority: Pubkey,
+        key_count: u64,
+        key: SparseKey,
+        value: Hash,
+        proof: SparseProof,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(find_sparse_tree_pda(&program_id).0, false),
+                AccountMeta::new(find_sparse_entry_pda(&key, &program_id).0, false),
+                AccountMeta::new(find_sparse_slot_pda(key_count, &program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+            ],
+            data: MTreeInstruction::SetSparseValue { key, value, proof }.try_to_vec()?,
+        })
+    }
+
+    /// Builds `DeleteSparseValue` for `key` listed in `slot` of a tree
+    /// holding `key_count` keys.
+    pub fn make_delete_sparse_value_instruction(
+        program_id: Pubkey,
+        authority: Pubkey,
+        receiver: Pubkey,
+        key_count: u64,
+        key: SparseKey,
+        slot: u64,
+        proof: SparseProof,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(find_sparse_tree_pda(&program_id).0, false),
+                AccountMeta::new(find_sparse_entry_pda(&key, &program_id).0, false),
+                AccountMeta::new(find_sparse_slot_pda(slot, &program_id).0, false),
+                AccountMeta::new(
+                    find_sparse_slot_pda(key_count.saturating_sub(1), &program_id).0,
+                    false,
+                ),
+                AccountMeta::new(receiver, false),
+            ],
+            data: MTreeInstruction::DeleteSparseValue { key, slot, proof }.try_to_vec()?,
+        })
+    }
+
+    /// Builds `AppendMmrLeaf` for the leaf that gets index `leaf_index`, the
+    /// current `leaf_count` of the range.
+    pub fn make_append_mmr_leaf_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        data: Vec<u8>,
+        leaf_index: u64,
+    ) -> Result<Instruction, io::Error> {
+        let page = leaf_index / MMR_PAGE_LEAVES;
+        Ok(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(author, true),
+                AccountMeta::new(find_info_pda(&program_id).0, false),
+                AccountMeta::new_readonly(solana_program::system_program::ID, false),
+                AccountMeta::new(find_mmr_page_pda(page, &program_id).0, false),
+            ],
+            data: MTreeInstruction::AppendMmrLeaf(data).try_to_vec()?,
+        })
+    }
+
+    pub fn make_verify_leaves_instruction(
+        program_id: Pubkey,
+        leaf_indexes: Vec<u64>,
+        leaves: Vec<Hash>,
+        proof: MerkleMultiProof,
+    ) -> Result<Instruction, io::Error> {
+        Ok(Instruction {
+            program_id,
+            accounts: vec![AccountMeta::new_readonly(
+                find_info_pda(&program_id).0,
+                false,
+            )],
+            data: MTreeInstruction::VerifyLeaves {
+                leaf_indexes,
+                leaves,
+                proof,
+            }
+            .try_to_vec()?,
+        })
+    }
+
+    /// Adds the leaf marker account, required by trees with `dedup` enabled,
+    /// to an instruction built by one of the `make_insert_*` functions.
+    pub fn add_leaf_marker_account(instruction: &mut Instruction, leaf_hash: &Hash) {
+        let marker = find_leaf_marker_pda(leaf_hash, &instruction.program_id).0;
+        // The marker follows the sub trees, the rent sysvar stays last.
+        let index = instruction.accounts.len() - 1;
+        instruction
+            .accounts
+            .insert(index, AccountMeta::new(marker, false));
+    }
+
+    /// Adds the sub tree accounts of the path of sub tree `id + 1` to an
+    /// instruction built by one of the `make_insert_*` functions for sub tree
+    /// `id`, so the insert still lands if the current sub tree fills up
+    /// before it.
+    pub fn add_next_sub_tree_accounts(instruction: &mut Instruction, id: SubTreeId) {
+        let Some(next_id) = id.checked_add(1) else {
+            return;
+        };
+        for node_id in get_path_to_root(next_id) {
+            let key = find_sub_tree_pda(node_id, &instruction.program_id).0;
+            if instruction
+                .accounts
+                .iter()
+                .all(|account| account.pubkey != key)
+            {
+                // The rent sysvar stays last.
+                let index = instruction.accounts.len() - 1;
+                instruction
+                    .accounts
+                    .insert(index, AccountMeta::new(key, false));
+            }
+        }
+    }
 
+    fn make_insert_instruction(
+        program_id: Pubkey,
+        payer: Pubkey,
+        author: Pubkey,
+        id: SubTreeId,
+        instruction: MTreeInstruction,
+    ) -> Result<Instruction, io::Error> {
+        let mut accounts = insert_leaf_account_metas(&program_id, payer, author, id);
         accounts.push(AccountMeta::new_readonly(
             solana_program::sysvar::rent::ID,
             false,
@@ -49,7 +714,18 @@ pub mod encode {
         Ok(Instruction {
             program_id,
             accounts,
-            data: MTreeInstruction::InsertLeaf(data).try_to_vec()?,
+            data: instruction.try_to_vec()?,
         })
     }
+
+    /// Decodes the data returned by `InsertLeaf`, e.g. the return data of a
+    /// simulated transaction.
+    pub fn decode_insert_leaf_return(data: &[u8]) -> Result<InsertLeafReturn, io::Error> {
+        InsertLeafReturn::from_return_data(data)
+    }
+
+    /// Reads the result of an `InsertLeaf` invoked through CPI.
+    pub fn get_insert_leaf_return(program_id: &Pubkey) -> Option<InsertLeafReturn> {
+        InsertLeafReturn::from_cpi(program_id)
+    }
 }
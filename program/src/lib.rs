@@ -1,4 +1,6 @@
 pub mod assertions;
+pub mod buffer;
+pub mod cpi;
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 pub mod error;
@@ -6,6 +8,8 @@ pub mod info;
 pub mod instruction;
 pub mod mtree;
 pub mod processor;
+pub mod receipt;
+pub mod return_data;
 pub mod events;
 pub use solana_program;
 
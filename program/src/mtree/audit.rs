@@ -0,0 +1,166 @@
+//! Integrity checks of the sub tree accounts of a tree.
+
+use super::path::get_child_index;
+use super::sub_tree::{SubTree, SUB_TREE_LEAFS};
+use super::{Hash, SubTreeId};
+use borsh::BorshDeserialize as _;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AuditIssue {
+    /// The account of a sub tree before the current one does not exist.
+    MissingSubTree(SubTreeId),
+    /// The account data is not a sub tree.
+    InvalidSubTree(SubTreeId),
+    /// The internal nodes do not match a recomputation from the leaves.
+    InconsistentNodes(SubTreeId),
+    /// The leaf of the parent holding the sub tree differs from its root.
+    ParentMismatch(SubTreeId),
+    /// The root of the root sub tree differs from the root of the info.
+    RootMismatch,
+}
+
+impl AuditIssue {
+    /// Returns the sub tree whose account is inconsistent, `None` for the
+    /// info account.
+    pub fn sub_tree_id(&self) -> Option<SubTreeId> {
+        match self {
+            Self::MissingSubTree(id)
+            | Self::InvalidSubTree(id)
+            | Self::InconsistentNodes(id)
+            | Self::ParentMismatch(id) => Some(*id),
+            Self::RootMismatch => None,
+        }
+    }
+}
+
+/// Checks the data of the sub tree accounts from 0 up to `tree_id`, `None`
+/// for the accounts that do not exist, against each other and against the
+/// `root_hash` of the info. Returns every issue found.
+pub fn audit_sub_trees(
+    tree_id: SubTreeId,
+    root_hash: &Hash,
+    sub_tree_data: &[Option<&[u8]>],
+) -> Vec<AuditIssue> {
+    let mut issues = Vec::new();
+
+    let mut sub_trees = Vec::with_capacity(sub_tree_data.len());
+    for (id, data) in (0..).zip(sub_tree_data) {
+        let sub_tree = match data {
+            // The current sub tree is created by the first insert into it.
+            None if id == tree_id => None,
+            None => {
+                issues.push(AuditIssue::MissingSubTree(id));
+                None
+            }
+            Some(data) => match SubTree::try_from_slice(data) {
+                Ok(sub_tree) if sub_tree.is_consistent() => Some(sub_tree),
+                Ok(_) => {
+                    issues.push(AuditIssue::InconsistentNodes(id));
+                    None
+                }
+                Err(_) => {
+                    issues.push(AuditIssue::InvalidSubTree(id));
+                    None
+                }
+            },
+        };
+        sub_trees.push(sub_tree);
+    }
+    if sub_trees.len() <= tree_id as usize {
+        issues.extend((sub_trees.len() as SubTreeId..tree_id).map(AuditIssue::MissingSubTree));
+    }
+
+    for (id, sub_tree) in (0..).zip(&sub_trees).skip(1) {
+        let parent_id = (id - 1) / SUB_TREE_LEAFS as SubTreeId;
+        if let (Some(sub_tree), Some(Some(parent))) = (sub_tree, sub_trees.get(parent_id as usize))
+        {
+            let parent_leaf = parent.get_leaf(get_child_index(id));
+            if parent_leaf != Some(sub_tree.root_hash()) {
+                issues.push(AuditIssue::ParentMismatch(id));
+            }
+        }
+    }
+
+    // An empty tree has the default root and no sub tree.
+    let root_sub_tree_hash = match sub_trees.first() {
+        Some(Some(root_sub_tree)) => Some(root_sub_tree.root_hash()),
+        Some(None) if tree_id == 0 => Some(Hash::default()),
+        _ => None,
+    };
+    if root_sub_tree_hash.is_some_and(|hash| hash != *root_hash) {
+        issues.push(AuditIssue::RootMismatch);
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtree::hash_leaf;
+    use crate::mtree::local::LocalTree;
+    use borsh::BorshSerialize as _;
+
+    fn sub_tree_data(tree: &LocalTree) -> Vec<Vec<u8>> {
+        tree.sub_trees()
+            .iter()
+            .map(|sub_tree| sub_tree.try_to_vec().unwrap())
+            .collect()
+    }
+
+    fn audit(tree: &LocalTree, data: &[Vec<u8>]) -> Vec<AuditIssue> {
+        let data = data
+            .iter()
+            .map(|data| Some(data.as_slice()))
+            .collect::<Vec<_>>();
+        audit_sub_trees(tree.tree_id(), &tree.root_hash(), &data)
+    }
+
+    #[test]
+    fn test_consistent_tree() {
+        let tree = LocalTree::new();
+        assert_eq!(audit_sub_trees(0, &tree.root_hash(), &[None]), vec![]);
+
+        let tree = LocalTree::from_leaves((0..100u64).map(|i| hash_leaf(i.to_le_bytes())));
+        assert_eq!(audit(&tree, &sub_tree_data(&tree)), vec![]);
+
+        // Fills the root sub tree, the account of sub tree 1 is not created.
+        let tree = LocalTree::from_leaves((0..7u64).map(|i| hash_leaf(i.to_le_bytes())));
+        let data = sub_tree_data(&tree);
+        assert_eq!(tree.tree_id(), 1);
+        assert_eq!(
+            audit_sub_trees(1, &tree.root_hash(), &[Some(&data[0]), None]),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_tree() {
+        let tree = LocalTree::from_leaves((0..100u64).map(|i| hash_leaf(i.to_le_bytes())));
+        let mut data = sub_tree_data(&tree);
+        let last = tree.tree_id() as usize;
+
+        // A leaf changed without its parents.
+        let mut sub_tree = tree.sub_trees()[2].clone();
+        sub_tree.update_leaf(SUB_TREE_LEAFS, hash_leaf([1]));
+        data[2] = sub_tree.try_to_vec().unwrap();
+        assert_eq!(audit(&tree, &data), vec![AuditIssue::ParentMismatch(2)]);
+
+        data[3] = vec![1, 2, 3];
+        let mut data = data
+            .iter()
+            .map(|data| Some(data.as_slice()))
+            .collect::<Vec<_>>();
+        data[4] = None;
+        data[last] = None;
+        assert_eq!(
+            audit_sub_trees(tree.tree_id(), &hash_leaf([2]), &data),
+            vec![
+                AuditIssue::InvalidSubTree(3),
+                AuditIssue::MissingSubTree(4),
+                AuditIssue::ParentMismatch(2),
+                AuditIssue::RootMismatch,
+            ]
+        );
+    }
+}
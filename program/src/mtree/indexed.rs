@@ -0,0 +1,356 @@
+use super::{join_hashes, proof::MerkleProof, Hash};
+use crate::error::MtreeError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::mem::size_of;
+use solana_program::hash::hashv;
+
+/// Number of hashing levels between the leaves and the root of an indexed
+/// tree, which holds up to `2^INDEXED_TREE_DEPTH` leaves.
+pub const INDEXED_TREE_DEPTH: usize = 20;
+
+/// Leaf of an indexed tree. The leaves form a linked list sorted by value,
+/// so a leaf proves that no value lies between its value and `next_value`.
+/// `next_index == 0` marks the leaf with the largest value.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct IndexedLeaf {
+    pub value: Hash,
+    pub next_index: u64,
+    pub next_value: Hash,
+}
+
+impl IndexedLeaf {
+    pub const LEN: usize = size_of::<Hash>() + size_of::<u64>() + size_of::<Hash>();
+
+    pub fn hash(&self) -> Hash {
+        hashv(&[
+            &self.value,
+            &self.next_index.to_le_bytes(),
+            &self.next_value,
+        ])
+        .to_bytes()
+    }
+
+    /// Returns true if `value` is not in the tree and would be inserted right
+    /// after this leaf.
+    pub fn is_low_leaf_of(&self, value: &Hash) -> bool {
+        self.value < *value && (self.next_index == 0 || *value < self.next_value)
+    }
+}
+
+/// Hashes of empty sub trees, `zero_hashes()[level]` being the node of an
+/// empty sub tree with `level` levels below it.
+pub fn zero_hashes() -> [Hash; INDEXED_TREE_DEPTH] {
+    let mut zeros = [Hash::default(); INDEXED_TREE_DEPTH];
+    for level in 1..INDEXED_TREE_DEPTH {
+        zeros[level] = join_hashes(&zeros[level - 1], &zeros[level - 1]);
+    }
+    zeros
+}
+
+/// On-chain state of an indexed tree. Only the rightmost left nodes of each
+/// level are stored, which is enough to append leaves.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct IndexedTree {
+    pub root: Hash,
+    pub next_index: u64,
+    frontier: [Hash; INDEXED_TREE_DEPTH],
+}
+
+impl Default for IndexedTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndexedTree {
+    pub const LEN: usize =
+        size_of::<Hash>() + size_of::<u64>() + INDEXED_TREE_DEPTH * size_of::<Hash>();
+
+    /// Creates a tree holding the zero leaf, which is the low leaf of every
+    /// value inserted first.
+    pub fn new() -> Self {
+        let mut tree = Self {
+            root: Hash::default(),
+            next_index: 0,
+            frontier: [Hash::default(); INDEXED_TREE_DEPTH],
+        };
+        tree.append(IndexedLeaf::default().hash(), &zero_hashes());
+        tree
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.next_index == 1 << INDEXED_TREE_DEPTH
+    }
+
+    /// Inserts `value` after the low leaf at `low_leaf_index`, whose proof is
+    /// checked against the current root. Returns the updated low leaf and the
+    /// new leaf, which is stored at the previous `next_index`.
+    pub fn insert(
+        &mut self,
+        value: Hash,
+        low_leaf: &IndexedLeaf,
+        low_leaf_index: u64,
+        low_leaf_proof: &[Hash],
+    ) -> Result<(IndexedLeaf, IndexedLeaf), MtreeError> {
+        if self.is_full() {
+            return Err(MtreeError::IndexedTreeFull);
+        }
+        if !low_leaf.is_low_leaf_of(&value) {
+            return Err(MtreeError::InvalidLowLeaf);
+        }
+
+        let proof = MerkleProof {
+            position: low_leaf_index,
+            siblings: low_leaf_proof.to_vec(),
+        };
+        if low_leaf_index >= self.next_index
+            || proof.siblings.len() != INDEXED_TREE_DEPTH
+            || !proof.verify(&self.root, low_leaf.hash())
+        {
+            return Err(MtreeError::InvalidLowLeafProof);
+        }
+
+        let new_leaf = IndexedLeaf {
+            value,
+            next_index: low_leaf.next_index,
+            next_value: low_leaf.next_value,
+        };
+        let new_low_leaf = IndexedLeaf {
+            value: low_leaf.value,
+            next_index: self.next_index,
+            next_value: value,
+        };
+
+        // The low leaf may lie below a stored frontier node, which has to be
+        // updated before the new leaf is appended.
+        let mut node = new_low_leaf.hash();
+        for (level, sibling) in proof.siblings.iter().enumerate() {
+            let low_node_index = low_leaf_index >> level;
+            let append_node_index = self.next_index >> level;
+            if append_node_index & 1 == 1 && low_node_index == append_node_index - 1 {
+                self.frontier[level] = node;
+            }
+
+            node = if low_node_index & 1 == 0 {
+                join_hashes(&node, sibling)
+            } else {
+                join_hashes(sibling, &node)
+            };
+        }
+
+        self.append(new_leaf.hash(), &zero_hashes());
+        Ok((new_low_leaf, new_leaf))
+    }
+
+    fn append(&mut self, leaf: Hash, zeros: &[Hash; INDEXED_TREE_DEPTH]) {
+        let mut node = leaf;
+        for (level, zero) in zeros.iter().enumerate() {
+            if (self.next_index >> level) & 1 == 0 {
+                self.frontier[level] = node;
+                node = join_hashes(&node, zero);
+            } else {
+                node = join_hashes(&self.frontier[level], &node);
+            }
+        }
+        self.root = node;
+        self.next_index += 1;
+    }
+}
+
+/// Leaf of an indexed tree with its inclusion proof. Depending on the leaf it
+/// proves that a value is in the tree, or that it is not.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct IndexedLeafProof {
+    pub leaf: IndexedLeaf,
+    pub proof: MerkleProof,
+}
+
+impl IndexedLeafProof {
+    /// Checks that `value` is in the tree with the given root.
+    pub fn verify_membership(&self, root: &Hash, value: &Hash) -> bool {
+        self.leaf.value == *value && self.verify_leaf(root)
+    }
+
+    /// Checks that `value` is not in the tree with the given root.
+    pub fn verify_non_membership(&self, root: &Hash, value: &Hash) -> bool {
+        self.leaf.is_low_leaf_of(value) && self.verify_leaf(root)
+    }
+
+    fn verify_leaf(&self, root: &Hash) -> bool {
+        self.proof.siblings.len() == INDEXED_TREE_DEPTH && self.proof.verify(root, self.leaf.hash())
+    }
+}
+
+/// Off-chain copy of an indexed tree, built from all of its leaves in index
+/// order.
+pub struct IndexedTreeLeaves {
+    leaves: Vec<IndexedLeaf>,
+    levels: Vec<Vec<Hash>>,
+}
+
+impl IndexedTreeLeaves {
+    pub fn new(leaves: Vec<IndexedLeaf>) -> Self {
+        let zeros = zero_hashes();
+        let mut levels = Vec::with_capacity(INDEXED_TREE_DEPTH + 1);
+        levels.push(leaves.iter().map(IndexedLeaf::hash).collect::<Vec<_>>());
+
+        for zero in zeros.iter() {
+            let nodes = levels.last().unwrap();
+            let parents = nodes
+                .chunks(2)
+                .map(|pair| join_hashes(&pair[0], pair.get(1).unwrap_or(zero)))
+                .collect();
+            levels.push(parents);
+        }
+
+        Self { leaves, levels }
+    }
+
+    pub fn leaves(&self) -> &[IndexedLeaf] {
+        &self.leaves
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels[INDEXED_TREE_DEPTH]
+            .first()
+            .copied()
+            .unwrap_or_else(|| {
+                let zero = zero_hashes()[INDEXED_TREE_DEPTH - 1];
+                join_hashes(&zero, &zero)
+            })
+    }
+
+    /// Returns the proof of the leaf at `index`.
+    pub fn get_proof(&self, index: u64) -> Option<MerkleProof> {
+        if index as usize >= self.leaves.len() {
+            return None;
+        }
+
+        let zeros = zero_hashes();
+        let siblings = self.levels[..INDEXED_TREE_DEPTH]
+            .iter()
+            .zip(zeros.iter())
+            .enumerate()
+            .map(|(level, (nodes, zero))| {
+                let sibling = ((index >> level) ^ 1) as usize;
+                nodes.get(sibling).copied().unwrap_or(*zero)
+            })
+            .collect();
+
+        Some(MerkleProof {
+            position: index,
+            siblings,
+        })
+    }
+
+    /// Returns the index of the leaf holding `value`.
+    pub fn find_leaf(&self, value: &Hash) -> Option<u64> {
+        self.leaves
+            .iter()
+            .position(|leaf| leaf.value == *value)
+            .map(|index| index as u64)
+    }
+
+    /// Returns the index of the low leaf of `value`, if `value` is not in the
+    /// tree.
+    pub fn find_low_leaf(&self, value: &Hash) -> Option<u64> {
+        self.leaves
+            .iter()
+            .position(|leaf| leaf.is_low_leaf_of(value))
+            .map(|index| index as u64)
+    }
+
+    pub fn membership_proof(&self, value: &Hash) -> Option<IndexedLeafProof> {
+        self.leaf_proof(self.find_leaf(value)?)
+    }
+
+    pub fn non_membership_proof(&self, value: &Hash) -> Option<IndexedLeafProof> {
+        self.leaf_proof(self.find_low_leaf(value)?)
+    }
+
+    fn leaf_proof(&self, index: u64) -> Option<IndexedLeafProof> {
+        Some(IndexedLeafProof {
+            leaf: self.leaves[index as usize],
+            proof: self.get_proof(index)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(byte: u8) -> Hash {
+        let mut value = Hash::default();
+        value[31] = byte;
+        value
+    }
+
+    #[test]
+    fn test_borsh_indexed_tree_size() {
+        let tree = IndexedTree::new();
+        assert_eq!(tree.try_to_vec().unwrap().len(), IndexedTree::LEN);
+        let leaf = IndexedLeaf::default();
+        assert_eq!(leaf.try_to_vec().unwrap().len(), IndexedLeaf::LEN);
+    }
+
+    #[test]
+    fn test_insert_matches_off_chain_tree() {
+        let mut tree = IndexedTree::new();
+        let mut leaves = vec![IndexedLeaf::default()];
+        assert_eq!(tree.root, IndexedTreeLeaves::new(leaves.clone()).root());
+
+        for byte in [50, 10, 30, 70, 20, 60, 40, 80, 5] {
+            let off_chain = IndexedTreeLeaves::new(leaves.clone());
+            let low_index = off_chain.find_low_leaf(&value(byte)).unwrap();
+            let proof = off_chain.get_proof(low_index).unwrap();
+
+            let (low_leaf, new_leaf) = tree
+                .insert(
+                    value(byte),
+                    &leaves[low_index as usize],
+                    low_index,
+                    &proof.siblings,
+                )
+                .unwrap();
+            leaves[low_index as usize] = low_leaf;
+            leaves.push(new_leaf);
+
+            assert_eq!(tree.root, IndexedTreeLeaves::new(leaves.clone()).root());
+        }
+        assert_eq!(tree.next_index, 10);
+
+        let off_chain = IndexedTreeLeaves::new(leaves.clone());
+        let proof = off_chain.membership_proof(&value(30)).unwrap();
+        assert!(proof.verify_membership(&tree.root, &value(30)));
+        assert!(!proof.verify_non_membership(&tree.root, &value(30)));
+        assert!(off_chain.non_membership_proof(&value(30)).is_none());
+
+        let proof = off_chain.non_membership_proof(&value(35)).unwrap();
+        assert_eq!(proof.leaf.value, value(30));
+        assert!(proof.verify_non_membership(&tree.root, &value(35)));
+        assert!(!proof.verify_non_membership(&tree.root, &value(45)));
+
+        let proof = off_chain.non_membership_proof(&value(90)).unwrap();
+        assert!(proof.verify_non_membership(&tree.root, &value(90)));
+    }
+
+    #[test]
+    fn test_insert_rejects_invalid_low_leaf() {
+        let mut tree = IndexedTree::new();
+        let leaves = IndexedTreeLeaves::new(vec![IndexedLeaf::default()]);
+        let proof = leaves.get_proof(0).unwrap();
+
+        let (low_leaf, _) = tree
+            .insert(value(10), &IndexedLeaf::default(), 0, &proof.siblings)
+            .unwrap();
+        assert_eq!(
+            tree.insert(value(20), &IndexedLeaf::default(), 0, &proof.siblings),
+            Err(MtreeError::InvalidLowLeafProof)
+        );
+        assert_eq!(
+            tree.insert(value(10), &low_leaf, 0, &proof.siblings),
+            Err(MtreeError::InvalidLowLeaf)
+        );
+    }
+}
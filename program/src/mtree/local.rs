@@ -0,0 +1,162 @@
+//! In-memory tree applying inserts exactly as `processor::insert_leaf` does,
+//! to compute roots and proofs without a chain.
+
+use super::path::{get_child_index, get_leaf_position, get_path_to_root};
+use super::proof::MerkleProof;
+use super::sub_tree::{SubTree, SUB_TREE_LEAFS};
+use super::{Hash, SubTreeId};
+
+#[derive(Clone, Debug, Default)]
+pub struct LocalTree {
+    /// Sub trees by id, created in order as the tree grows.
+    sub_trees: Vec<SubTree>,
+    tree_id: SubTreeId,
+    root_hash: Hash,
+    leaf_count: u64,
+}
+
+impl LocalTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the tree holding the given leaves, in insertion order.
+    pub fn from_leaves<I: IntoIterator<Item = Hash>>(leaves: I) -> Self {
+        let mut tree = Self::new();
+        for leaf in leaves {
+            tree.insert_leaf(leaf);
+        }
+        tree
+    }
+
+    /// Id of the sub tree the next leaf goes to, as `MTreeInfo::tree_id`.
+    pub fn tree_id(&self) -> SubTreeId {
+        self.tree_id
+    }
+
+    /// Root of the tree, as `MTreeInfo::root_hash`: the default hash until
+    /// the first insert.
+    pub fn root_hash(&self) -> Hash {
+        self.root_hash
+    }
+
+    pub fn leaf_count(&self) -> u64 {
+        self.leaf_count
+    }
+
+    pub fn sub_trees(&self) -> &[SubTree] {
+        &self.sub_trees
+    }
+
+    pub fn sub_tree(&self, id: SubTreeId) -> Option<&SubTree> {
+        self.sub_trees.get(id as usize)
+    }
+
+    /// Appends a leaf and returns its global index.
+    pub fn insert_leaf(&mut self, leaf_hash: Hash) -> u64 {
+        let path = get_path_to_root(self.tree_id);
+        let last_sub_tree_id = path[0];
+        if self.sub_trees.len() == last_sub_tree_id as usize {
+            self.sub_trees.push(SubTree::new());
+        }
+
+        // A new sub tree takes the place of a leaf of its parent, so the leaf
+        // is moved down into the first position of the new sub tree.
+        if self.sub_trees[last_sub_tree_id as usize].is_empty() {
+            if let Some(parent_id) = path.get(1) {
+                if let Some(leaf) =
+                    self.sub_trees[*parent_id as usize].get_leaf(get_child_index(last_sub_tree_id))
+                {
+                    self.sub_trees[last_sub_tree_id as usize].insert_leaf(leaf);
+                }
+            }
+        }
+
+        let leaf_sub_tree = &mut self.sub_trees[last_sub_tree_id as usize];
+        leaf_sub_tree.insert_leaf(leaf_hash);
+        if leaf_sub_tree.is_full() {
+            self.tree_id = last_sub_tree_id + 1;
+        }
+
+        let mut root_hash = leaf_sub_tree.root_hash();
+        let mut child_id = last_sub_tree_id;
+        for tree_id in path.iter().skip(1) {
+            let sub_tree = &mut self.sub_trees[*tree_id as usize];
+            sub_tree.update_leaf(SUB_TREE_LEAFS - 1 + get_child_index(child_id), root_hash);
+            root_hash = sub_tree.root_hash();
+            child_id = *tree_id;
+        }
+
+        self.root_hash = root_hash;
+        self.leaf_count += 1;
+        self.leaf_count - 1
+    }
+
+    /// Returns the hash of the leaf with the given global index.
+    pub fn get_leaf(&self, leaf_index: u64) -> Option<Hash> {
+        let (id, position) = self.leaf_position(leaf_index)?;
+        self.sub_tree(id)?.get_leaf(position)
+    }
+
+    /// Returns the proof of the leaf with the given global index against the
+    /// current root.
+    pub fn get_proof(&self, leaf_index: u64) -> Option<MerkleProof> {
+        let (id, position) = self.leaf_position(leaf_index)?;
+        let path = get_path_to_root(id);
+        let sub_trees = path
+            .iter()
+            .map(|id| self.sub_tree(*id).cloned())
+            .collect::<Option<Vec<_>>>()?;
+        MerkleProof::from_sub_trees(&path, &sub_trees, position)
+    }
+
+    fn leaf_position(&self, leaf_index: u64) -> Option<(SubTreeId, usize)> {
+        (leaf_index < self.leaf_count).then(|| get_leaf_position(leaf_index, self.leaf_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtree::hash_leaf;
+
+    #[test]
+    fn test_relocated_leaf() {
+        let mut tree = LocalTree::new();
+        let mut root_sub_tree = SubTree::new();
+        for i in 0..SUB_TREE_LEAFS - 1 {
+            tree.insert_leaf(hash_leaf([i as u8]));
+            root_sub_tree.insert_leaf(hash_leaf([i as u8]));
+        }
+        assert_eq!(tree.tree_id(), 1);
+        assert_eq!(tree.root_hash(), root_sub_tree.root_hash());
+
+        let mut sub_tree = SubTree::new();
+        sub_tree.insert_leaf(root_sub_tree.get_leaf(0).unwrap());
+        sub_tree.insert_leaf(hash_leaf([100]));
+        root_sub_tree.update_leaf(SUB_TREE_LEAFS - 1, sub_tree.root_hash());
+
+        assert_eq!(
+            tree.insert_leaf(hash_leaf([100])),
+            SUB_TREE_LEAFS as u64 - 1
+        );
+        assert_eq!(tree.root_hash(), root_sub_tree.root_hash());
+        assert_eq!(tree.get_leaf(0), Some(hash_leaf([0])));
+    }
+
+    #[test]
+    fn test_proofs() {
+        let mut tree = LocalTree::new();
+        assert_eq!(tree.root_hash(), Hash::default());
+
+        for i in 0..300u64 {
+            assert_eq!(tree.insert_leaf(hash_leaf(i.to_le_bytes())), i);
+            for j in (0..=i).step_by(7) {
+                let leaf = hash_leaf(j.to_le_bytes());
+                assert_eq!(tree.get_leaf(j), Some(leaf));
+                assert!(tree.get_proof(j).unwrap().verify(&tree.root_hash(), leaf));
+            }
+        }
+        assert_eq!(tree.get_proof(300), None);
+    }
+}
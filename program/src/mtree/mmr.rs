@@ -0,0 +1,423 @@
+use super::{join_hashes, Hash};
+use crate::error::MtreeError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::mem::size_of;
+
+/// Maximum number of peaks, which limits the range to `2^MMR_MAX_PEAKS - 1`
+/// leaves.
+pub const MMR_MAX_PEAKS: usize = 32;
+
+/// Number of leaf hashes stored by each MMR page account.
+pub const MMR_PAGE_LEAVES: u64 = 256;
+
+/// Size of an MMR page account: the hashes of its leaves, in order.
+pub const MMR_PAGE_SIZE: usize = MMR_PAGE_LEAVES as usize * size_of::<Hash>();
+
+/// Bags peaks given from the lowest to the highest into the root:
+/// `H(P_high || H(.. || P_low))`. The root of an empty range is zero.
+pub fn bag_peaks(peaks: &[Hash]) -> Hash {
+    peaks
+        .iter()
+        .copied()
+        .reduce(|lower, peak| join_hashes(&peak, &lower))
+        .unwrap_or_default()
+}
+
+/// Returns the `(height, first leaf index)` of the peaks of a range of
+/// `leaf_count` leaves, from the highest peak to the lowest.
+pub fn peak_ranges(leaf_count: u64) -> Vec<(usize, u64)> {
+    let mut start = 0;
+    (0..u64::BITS as usize)
+        .rev()
+        .filter(|height| leaf_count >> height & 1 == 1)
+        .map(|height| {
+            let range = (height, start);
+            start += 1 << height;
+            range
+        })
+        .collect()
+}
+
+/// Merkle mountain range accumulator. Appending a leaf only merges the peaks
+/// of equal height, nodes already in the range never change, so every root
+/// can be proven consistent with the later ones.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct Mmr {
+    pub leaf_count: u64,
+    pub root: Hash,
+    /// `peaks[height]` is the peak of that height if bit `height` of
+    /// `leaf_count` is set.
+    peaks: [Hash; MMR_MAX_PEAKS],
+}
+
+impl Default for Mmr {
+    fn default() -> Self {
+        Self {
+            leaf_count: 0,
+            root: Hash::default(),
+            peaks: [Hash::default(); MMR_MAX_PEAKS],
+        }
+    }
+}
+
+impl Mmr {
+    pub const LEN: usize = size_of::<u64>() + size_of::<Hash>() + MMR_MAX_PEAKS * size_of::<Hash>();
+
+    pub fn is_full(&self) -> bool {
+        self.leaf_count == (1 << MMR_MAX_PEAKS) - 1
+    }
+
+    pub fn append(&mut self, leaf: Hash) -> Result<(), MtreeError> {
+        if self.is_full() {
+            return Err(MtreeError::MmrFull);
+        }
+
+        let mut node = leaf;
+        let mut height = 0;
+        while self.leaf_count >> height & 1 == 1 {
+            node = join_hashes(&self.peaks[height], &node);
+            self.peaks[height] = Hash::default();
+            height += 1;
+        }
+        self.peaks[height] = node;
+
+        self.leaf_count += 1;
+        self.root = bag_peaks(&self.peaks());
+        Ok(())
+    }
+
+    /// Returns the peaks from the lowest to the highest.
+    pub fn peaks(&self) -> Vec<Hash> {
+        (0..MMR_MAX_PEAKS)
+            .filter(|height| self.leaf_count >> height & 1 == 1)
+            .map(|height| self.peaks[height])
+            .collect()
+    }
+}
+
+/// Inclusion proof of a leaf in a range of `leaf_count` leaves: the siblings
+/// up to the peak of the leaf, from the leaf, and the other peaks, from the
+/// lowest to the highest.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MmrProof {
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    pub siblings: Vec<Hash>,
+    pub peaks: Vec<Hash>,
+}
+
+impl MmrProof {
+    /// Computes the root implied by the proof for the given leaf hash, or
+    /// `None` if the proof does not fit the range.
+    pub fn root(&self, leaf: Hash) -> Option<Hash> {
+        let (height, start) = peak_ranges(self.leaf_count)
+            .into_iter()
+            .find(|(height, start)| self.leaf_index - start < 1 << height)
+            .filter(|_| self.leaf_index < self.leaf_count)?;
+        if self.siblings.len() != height
+            || self.peaks.len() + 1 != self.leaf_count.count_ones() as usize
+        {
+            return None;
+        }
+
+        let offset = self.leaf_index - start;
+        let peak = self
+            .siblings
+            .iter()
+            .enumerate()
+            .fold(leaf, |node, (level, sibling)| {
+                if offset >> level & 1 == 0 {
+                    join_hashes(&node, sibling)
+                } else {
+                    join_hashes(sibling, &node)
+                }
+            });
+
+        let lower_peaks = (self.leaf_count & ((1 << height) - 1)).count_ones() as usize;
+        let mut peaks = self.peaks.clone();
+        peaks.insert(lower_peaks, peak);
+        Some(bag_peaks(&peaks))
+    }
+
+    pub fn verify(&self, root: &Hash, leaf: Hash) -> bool {
+        self.root(leaf) == Some(*root)
+    }
+}
+
+/// Proof that the range of `new_leaf_count` leaves extends the range of
+/// `old_leaf_count` leaves. New peaks fully inside the old range are old
+/// peaks; at most one new peak is partly inside, and it is rebuilt from the
+/// lowest old peak, the old peaks on its left and `siblings` on its right.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MmrConsistencyProof {
+    pub old_leaf_count: u64,
+    pub new_leaf_count: u64,
+    /// Peaks of the old range, from the lowest to the highest.
+    pub old_peaks: Vec<Hash>,
+    /// Right siblings from the lowest old peak up to its new peak.
+    pub siblings: Vec<Hash>,
+    /// New peaks holding only new leaves, from the lowest to the highest.
+    pub new_peaks: Vec<Hash>,
+}
+
+impl MmrConsistencyProof {
+    /// Computes the old and new roots implied by the proof, or `None` if the
+    /// proof does not fit the two ranges.
+    pub fn roots(&self) -> Option<(Hash, Hash)> {
+        if self.old_leaf_count > self.new_leaf_count {
+            return None;
+        }
+        let old_ranges = peak_ranges(self.old_leaf_count);
+        if self.old_peaks.len() != old_ranges.len() {
+            return None;
+        }
+
+        // Old peaks from the highest to the lowest, like the ranges.
+        let mut old_peaks = self.old_peaks.iter().rev();
+        let mut siblings = self.siblings.iter();
+        let mut new_peaks = self.new_peaks.iter();
+        let mut peaks = Vec::new();
+        for (height, start) in peak_ranges(self.new_leaf_count) {
+            if start + (1 << height) <= self.old_leaf_count {
+                peaks.push(*old_peaks.next()?);
+            } else if start < self.old_leaf_count {
+                let remaining = old_peaks.by_ref().copied().collect::<Vec<_>>();
+                let (&lowest, mut left) = remaining.split_last()?;
+                let (lowest_height, lowest_start) = *old_ranges.last()?;
+                let offset = lowest_start - start;
+
+                let mut node = lowest;
+                for level in lowest_height..height {
+                    node = if offset >> level & 1 == 0 {
+                        join_hashes(&node, siblings.next()?)
+                    } else {
+                        let (sibling, rest) = left.split_last()?;
+                        left = rest;
+                        join_hashes(sibling, &node)
+                    };
+                }
+                if !left.is_empty() {
+                    return None;
+                }
+                peaks.push(node);
+            } else {
+                peaks.push(*new_peaks.next_back()?);
+            }
+        }
+
+        if old_peaks.next().is_some() || siblings.next().is_some() || new_peaks.next().is_some() {
+            return None;
+        }
+        peaks.reverse();
+        Some((bag_peaks(&self.old_peaks), bag_peaks(&peaks)))
+    }
+
+    pub fn verify(&self, old_root: &Hash, new_root: &Hash) -> bool {
+        self.roots() == Some((*old_root, *new_root))
+    }
+}
+
+/// Off-chain copy of a Merkle mountain range, built from its leaf hashes in
+/// insertion order.
+#[derive(Clone, Debug, Default)]
+pub struct MmrLeaves {
+    leaves: Vec<Hash>,
+}
+
+impl MmrLeaves {
+    pub fn new(leaves: Vec<Hash>) -> Self {
+        Self { leaves }
+    }
+
+    pub fn leaves(&self) -> &[Hash] {
+        &self.leaves
+    }
+
+    pub fn push(&mut self, leaf: Hash) {
+        self.leaves.push(leaf);
+    }
+
+    /// Returns the root of the range holding the first `leaf_count` leaves.
+    pub fn root_at(&self, leaf_count: u64) -> Option<Hash> {
+        Some(bag_peaks(&self.peaks_at(leaf_count)?))
+    }
+
+    pub fn root(&self) -> Hash {
+        bag_peaks(&self.peaks_at(self.leaves.len() as u64).unwrap_or_default())
+    }
+
+    /// Returns the peaks of the range holding the first `leaf_count` leaves,
+    /// from the lowest to the highest.
+    pub fn peaks_at(&self, leaf_count: u64) -> Option<Vec<Hash>> {
+        if leaf_count > self.leaves.len() as u64 {
+            return None;
+        }
+
+        Some(
+            peak_ranges(leaf_count)
+                .into_iter()
+                .rev()
+                .map(|(height, start)| self.node(height, start))
+                .collect(),
+        )
+    }
+
+    /// Returns the proof of the leaf at `leaf_index` against the root of the
+    /// range holding the first `leaf_count` leaves.
+    pub fn get_proof(&self, leaf_index: u64, leaf_count: u64) -> Option<MmrProof> {
+        if leaf_index >= leaf_count || leaf_count > self.leaves.len() as u64 {
+            return None;
+        }
+
+        let ranges = peak_ranges(leaf_count);
+        let (height, start) = *ranges
+            .iter()
+            .find(|(height, start)| leaf_index - start < 1 << height)?;
+
+        let offset = leaf_index - start;
+        let siblings = (0..height)
+            .map(|level| self.node(level, start + ((offset >> level ^ 1) << level)))
+            .collect();
+        let peaks = ranges
+            .iter()
+            .rev()
+            .filter(|(_, peak_start)| *peak_start != start)
+            .map(|(height, start)| self.node(*height, *start))
+            .collect();
+
+        Some(MmrProof {
+            leaf_index,
+            leaf_count,
+            siblings,
+            peaks,
+        })
+    }
+
+    /// Returns the proof that the range holding the first `new_leaf_count`
+    /// leaves extends the range holding the first `old_leaf_count` leaves.
+    pub fn get_consistency_proof(
+        &self,
+        old_leaf_count: u64,
+        new_leaf_count: u64,
+    ) -> Option<MmrConsistencyProof> {
+        if old_leaf_count > new_leaf_count || new_leaf_count > self.leaves.len() as u64 {
+            return None;
+        }
+        let old_peaks = self.peaks_at(old_leaf_count)?;
+        let new_ranges = peak_ranges(new_leaf_count);
+
+        let mut siblings = Vec::new();
+        let partial = new_ranges.iter().find(|(height, start)| {
+            *start < old_leaf_count && old_leaf_count < start + (1 << height)
+        });
+        if let (Some(&(height, start)), Some(&(lowest_height, lowest_start))) =
+            (partial, peak_ranges(old_leaf_count).last())
+        {
+            let offset = lowest_start - start;
+            for level in lowest_height..height {
+                if offset >> level & 1 == 0 {
+                    siblings.push(self.node(level, start + ((offset >> level ^ 1) << level)));
+                }
+            }
+        }
+
+        let new_peaks = new_ranges
+            .iter()
+            .rev()
+            .filter(|(_, start)| *start >= old_leaf_count)
+            .map(|(height, start)| self.node(*height, *start))
+            .collect();
+
+        Some(MmrConsistencyProof {
+            old_leaf_count,
+            new_leaf_count,
+            old_peaks,
+            siblings,
+            new_peaks,
+        })
+    }
+
+    /// Hashes the perfect sub tree of the given height starting at `start`.
+    fn node(&self, height: usize, start: u64) -> Hash {
+        if height == 0 {
+            return self.leaves[start as usize];
+        }
+        let half = 1 << (height - 1);
+        join_hashes(
+            &self.node(height - 1, start),
+            &self.node(height - 1, start + half),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtree::hash_leaf;
+
+    #[test]
+    fn test_borsh_mmr_size() {
+        let mmr = Mmr::default();
+        assert_eq!(mmr.try_to_vec().unwrap().len(), Mmr::LEN);
+    }
+
+    #[test]
+    fn test_peak_ranges() {
+        assert_eq!(peak_ranges(0), vec![]);
+        assert_eq!(peak_ranges(11), vec![(3, 0), (1, 8), (0, 10)]);
+    }
+
+    #[test]
+    fn test_append_matches_off_chain_range() {
+        let mut mmr = Mmr::default();
+        let mut leaves = MmrLeaves::default();
+        assert_eq!(mmr.root, leaves.root());
+
+        for i in 0..20u8 {
+            let leaf = hash_leaf([i]);
+            mmr.append(leaf).unwrap();
+            leaves.push(leaf);
+            assert_eq!(mmr.root, leaves.root());
+            assert_eq!(mmr.peaks(), leaves.peaks_at(i as u64 + 1).unwrap());
+        }
+
+        for count in 1..=20 {
+            let root = leaves.root_at(count).unwrap();
+            for index in 0..count {
+                let proof = leaves.get_proof(index, count).unwrap();
+                assert!(proof.verify(&root, hash_leaf([index as u8])));
+                assert!(!proof.verify(&root, hash_leaf([index as u8 + 1])));
+            }
+        }
+        assert!(leaves.get_proof(20, 20).is_none());
+    }
+
+    #[test]
+    fn test_consistency_proofs() {
+        let leaves = MmrLeaves::new((0..20u8).map(|i| hash_leaf([i])).collect());
+
+        for new_count in 0..=20 {
+            let new_root = leaves.root_at(new_count).unwrap();
+            for old_count in 0..=new_count {
+                let old_root = leaves.root_at(old_count).unwrap();
+                let proof = leaves.get_consistency_proof(old_count, new_count).unwrap();
+                assert!(proof.verify(&old_root, &new_root));
+                assert!(!proof.verify(&new_root, &old_root) || old_root == new_root);
+            }
+        }
+
+        let mut proof = leaves.get_consistency_proof(5, 12).unwrap();
+        assert!(!proof.siblings.is_empty());
+        proof.siblings[0] = hash_leaf([99]);
+        assert!(!proof.verify(&leaves.root_at(5).unwrap(), &leaves.root_at(12).unwrap()));
+
+        // The old peaks must be the ones the new range is built from.
+        let other = MmrLeaves::new((1..21u8).map(|i| hash_leaf([i])).collect());
+        let mut proof = leaves.get_consistency_proof(5, 12).unwrap();
+        proof.old_peaks = other.peaks_at(5).unwrap();
+        assert!(!proof.verify(&other.root_at(5).unwrap(), &leaves.root_at(12).unwrap()));
+
+        assert!(leaves.get_consistency_proof(6, 5).is_none());
+        assert!(leaves.get_consistency_proof(5, 21).is_none());
+    }
+}
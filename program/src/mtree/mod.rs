@@ -1,13 +1,25 @@
 use solana_program::hash::hashv;
+use solana_program::pubkey::Pubkey;
 
+pub mod audit;
+pub mod indexed;
+pub mod local;
+pub mod mmr;
 pub mod path;
+pub mod sparse;
+pub mod proof;
 pub mod sub_tree;
 
 pub type SubTreeId = u32;
 pub type Hash = [u8; 32];
 
-pub fn hash_leaf(leaf: Vec<u8>) -> Hash {
-    hashv(&[leaf.as_slice()]).to_bytes()
+pub fn hash_leaf<T: AsRef<[u8]>>(leaf: T) -> Hash {
+    hashv(&[leaf.as_ref()]).to_bytes()
+}
+
+/// Hashes a leaf bound to its author: `H(author || leaf)`.
+pub fn hash_authored_leaf(author: &Pubkey, leaf: &[u8]) -> Hash {
+    hashv(&[author.as_ref(), leaf]).to_bytes()
 }
 
 pub fn join_hashes(left: &Hash, right: &Hash) -> Hash {
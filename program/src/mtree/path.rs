@@ -21,6 +21,51 @@ pub fn get_child_index(node_id: SubTreeId) -> usize {
     ((node_id - 1) % SUB_TREE_LEAFS as SubTreeId) as usize
 }
 
+/// Number of leaves inserted directly into the root sub tree.
+const ROOT_SUB_TREE_LEAVES: u64 = SUB_TREE_LEAFS as u64 - 1;
+/// Number of leaves inserted directly into any other sub tree. The first
+/// position is taken by the leaf relocated from the parent sub tree.
+const CHILD_SUB_TREE_LEAVES: u64 = SUB_TREE_LEAFS as u64 - 2;
+
+/// Returns the global index of the first leaf inserted into the sub tree.
+pub fn get_first_leaf_index(id: SubTreeId) -> u64 {
+    if id == 0 {
+        return 0;
+    }
+    ROOT_SUB_TREE_LEAVES + (id as u64 - 1) * CHILD_SUB_TREE_LEAVES
+}
+
+/// Returns the `(sub tree id, leaf position)` the leaf with the given global
+/// index is written to at insertion time.
+pub fn get_insert_position(leaf_index: u64) -> (SubTreeId, usize) {
+    if leaf_index < ROOT_SUB_TREE_LEAVES {
+        return (0, leaf_index as usize);
+    }
+    let index = leaf_index - ROOT_SUB_TREE_LEAVES;
+    (
+        (1 + index / CHILD_SUB_TREE_LEAVES) as SubTreeId,
+        (1 + index % CHILD_SUB_TREE_LEAVES) as usize,
+    )
+}
+
+/// Returns the `(sub tree id, leaf position)` of the leaf with the given
+/// global index in a tree holding `leaf_count` leaves.
+///
+/// A leaf is moved to the first position of the child sub tree that replaces
+/// it once that child is created, and may be moved again by the child's own
+/// first child.
+pub fn get_leaf_position(leaf_index: u64, leaf_count: u64) -> (SubTreeId, usize) {
+    let (mut id, mut position) = get_insert_position(leaf_index);
+    loop {
+        let child = id as u64 * SUB_TREE_LEAFS as u64 + 1 + position as u64;
+        if child > SubTreeId::MAX as u64 || get_first_leaf_index(child as SubTreeId) >= leaf_count {
+            return (id, position);
+        }
+        id = child as SubTreeId;
+        position = 0;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -48,4 +93,25 @@ mod tests {
         assert_eq!(get_child_index(2), 1); 
         assert_eq!(get_child_index(0), 0); 
     }
+
+    #[test]
+    fn test_get_insert_position() {
+        assert_eq!(get_insert_position(0), (0, 0));
+        assert_eq!(get_insert_position(6), (0, 6));
+        assert_eq!(get_insert_position(7), (1, 1));
+        assert_eq!(get_insert_position(12), (1, 6));
+        assert_eq!(get_insert_position(13), (2, 1));
+        assert_eq!(get_first_leaf_index(2), 13);
+    }
+
+    #[test]
+    fn test_get_leaf_position() {
+        assert_eq!(get_leaf_position(0, 7), (0, 0));
+        assert_eq!(get_leaf_position(0, 8), (1, 0));
+        assert_eq!(get_leaf_position(1, 13), (0, 1));
+        assert_eq!(get_leaf_position(1, 14), (2, 0));
+        assert_eq!(get_leaf_position(7, 56), (1, 1));
+        assert_eq!(get_leaf_position(0, 55), (1, 0));
+        assert_eq!(get_leaf_position(0, 56), (9, 0));
+    }
 }
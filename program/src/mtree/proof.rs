@@ -0,0 +1,424 @@
+use super::{
+    hash_authored_leaf, hash_leaf, join_hashes,
+    path::{get_child_index, get_leaf_position, get_path_to_root},
+    sub_tree::{SubTree, SUB_TREE_DEPTH},
+    Hash, SubTreeId,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+
+/// Inclusion proof of a single leaf.
+///
+/// `position` holds one bit per level, starting at the leaf: `0` if the node
+/// is the left child of its parent, `1` if it is the right child.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MerkleProof {
+    pub position: u64,
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Builds the proof of the leaf at `position` of the sub tree `path[0]`.
+    /// `path` is the path from that sub tree to the root, as returned by
+    /// `get_path_to_root`, and `sub_trees` holds the sub trees in the same
+    /// order.
+    pub fn from_sub_trees(
+        path: &[SubTreeId],
+        sub_trees: &[SubTree],
+        position: usize,
+    ) -> Option<Self> {
+        if path.len() != sub_trees.len() {
+            return None;
+        }
+
+        let mut proof = MerkleProof::default();
+        let mut index = position;
+        for (level, (id, sub_tree)) in path.iter().zip(sub_trees).enumerate() {
+            proof.siblings.extend(sub_tree.get_proof(index)?);
+            proof.position |= (index as u64) << (level * SUB_TREE_DEPTH);
+            index = get_child_index(*id);
+        }
+        Some(proof)
+    }
+
+    /// Computes the root implied by the proof for the given leaf hash.
+    pub fn root(&self, leaf: Hash) -> Hash {
+        self.siblings
+            .iter()
+            .enumerate()
+            .fold(leaf, |hash, (level, sibling)| {
+                if self.position.checked_shr(level as u32).unwrap_or(0) & 1 == 0 {
+                    join_hashes(&hash, sibling)
+                } else {
+                    join_hashes(sibling, &hash)
+                }
+            })
+    }
+
+    pub fn verify(&self, root: &Hash, leaf: Hash) -> bool {
+        self.root(leaf) == *root
+    }
+
+    /// Returns whether the proof starts at the leaf with the given global
+    /// index in a tree holding `leaf_count` leaves: both its position and its
+    /// number of siblings must match the place of that leaf, so a proof cut
+    /// short at an inner node is rejected.
+    pub fn is_for_leaf(&self, leaf_index: u64, leaf_count: u64) -> bool {
+        if leaf_index >= leaf_count {
+            return false;
+        }
+
+        let (id, position) = get_leaf_position(leaf_index, leaf_count);
+        MultiProofLeaf::from_sub_tree_position(id, position).is_some_and(|place| {
+            usize::from(place.depth) == self.siblings.len() && place.position == self.position
+        })
+    }
+}
+
+/// Place of a leaf in a `MerkleMultiProof`: `depth` is the number of levels
+/// between the leaf and the root, and `position` holds one bit per level as
+/// in `MerkleProof`.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MultiProofLeaf {
+    pub depth: u8,
+    pub position: u64,
+}
+
+impl MultiProofLeaf {
+    /// Returns the place of the leaf at `position` of the sub tree `id`, or
+    /// `None` if it is too deep to be described.
+    pub fn from_sub_tree_position(id: SubTreeId, position: usize) -> Option<Self> {
+        let path = get_path_to_root(id);
+        let depth = u8::try_from(path.len() * SUB_TREE_DEPTH).ok()?;
+
+        let mut leaf_position = 0u64;
+        let mut index = position;
+        for (level, id) in path.iter().enumerate() {
+            leaf_position |= (index as u64).checked_shl((level * SUB_TREE_DEPTH) as u32)?;
+            index = get_child_index(*id);
+        }
+        Some(Self {
+            depth,
+            position: leaf_position,
+        })
+    }
+}
+
+/// Inclusion proof of several leaves, storing each sibling hash once.
+///
+/// Nodes are joined level by level, from the deepest leaves up to the root
+/// and by `position` within a level. Each join takes one flag: `true` if the
+/// sibling is a node computed from the leaves, `false` if it is the next
+/// hash of `siblings`.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MerkleMultiProof {
+    pub leaves: Vec<MultiProofLeaf>,
+    pub siblings: Vec<Hash>,
+    pub flags: Vec<bool>,
+}
+
+impl MerkleMultiProof {
+    /// Merges the proofs of several leaves of the same tree.
+    pub fn from_proofs(proofs: &[MerkleProof]) -> Option<Self> {
+        let leaves = proofs
+            .iter()
+            .map(|proof| {
+                Some(MultiProofLeaf {
+                    depth: u8::try_from(proof.siblings.len()).ok()?,
+                    position: proof.position,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut siblings = Vec::new();
+        let mut flags = Vec::new();
+        join_levels(
+            &leaves,
+            (0..proofs.len()).collect(),
+            |_, _| true,
+            |depth, _, node, sibling| {
+                flags.push(sibling.is_some());
+                if sibling.is_none() {
+                    let proof = &proofs[node];
+                    let level = proof.siblings.len() - depth as usize;
+                    siblings.push(proof.siblings[level]);
+                }
+                Some(node)
+            },
+        )?;
+
+        Some(Self {
+            leaves,
+            siblings,
+            flags,
+        })
+    }
+
+    /// Computes the root implied by the proof for the given leaf hashes, in
+    /// the order of `leaves`, or `None` if the proof does not fit them.
+    pub fn root(&self, leaves: &[Hash]) -> Option<Hash> {
+        if leaves.len() != self.leaves.len() {
+            return None;
+        }
+
+        let mut siblings = self.siblings.iter();
+        let mut flags = self.flags.iter();
+        let root = join_levels(
+            &self.leaves,
+            leaves.to_vec(),
+            |a, b| a == b,
+            |_, position, node, sibling| {
+                if *flags.next()? != sibling.is_some() {
+                    return None;
+                }
+                let sibling = match sibling {
+                    Some(sibling) => sibling,
+                    None => *siblings.next()?,
+                };
+                Some(if position & 1 == 0 {
+                    join_hashes(&node, &sibling)
+                } else {
+                    join_hashes(&sibling, &node)
+                })
+            },
+        )?;
+
+        if siblings.next().is_some() || flags.next().is_some() {
+            return None;
+        }
+        Some(root)
+    }
+
+    pub fn verify(&self, root: &Hash, leaves: &[Hash]) -> bool {
+        self.root(leaves) == Some(*root)
+    }
+}
+
+/// Joins the nodes holding `values` at the places of `leaves` up to the root.
+/// Nodes at the same place must be `same` and are kept once. `join` gets the
+/// depth and position of a node, its value and the value of its sibling if
+/// the sibling is also computed, and returns the value of their parent.
+fn join_levels<T: Copy>(
+    leaves: &[MultiProofLeaf],
+    values: Vec<T>,
+    same: impl Fn(&T, &T) -> bool,
+    mut join: impl FnMut(u8, u64, T, Option<T>) -> Option<T>,
+) -> Option<T> {
+    if leaves.is_empty()
+        || leaves.iter().any(|leaf| {
+            leaf.depth > 64 || leaf.position.checked_shr(leaf.depth as u32).unwrap_or(0) != 0
+        })
+    {
+        return None;
+    }
+
+    let mut nodes = leaves
+        .iter()
+        .zip(values)
+        .map(|(leaf, value)| (leaf.depth, leaf.position, value))
+        .collect::<Vec<_>>();
+    nodes.sort_by_key(|(depth, position, _)| (u8::MAX - depth, *position));
+
+    let mut depth = nodes[0].0;
+    let mut level = Vec::new();
+    let mut next = 0;
+    loop {
+        while next < nodes.len() && nodes[next].0 == depth {
+            level.push((nodes[next].1, nodes[next].2));
+            next += 1;
+        }
+        level.sort_by_key(|(position, _)| *position);
+        if level
+            .windows(2)
+            .any(|w| w[0].0 == w[1].0 && !same(&w[0].1, &w[1].1))
+        {
+            return None;
+        }
+        level.dedup_by_key(|(position, _)| *position);
+
+        if depth == 0 {
+            return level.pop().map(|(_, value)| value);
+        }
+
+        let mut parents = Vec::with_capacity(level.len());
+        let mut i = 0;
+        while i < level.len() {
+            let (position, value) = level[i];
+            let sibling = level
+                .get(i + 1)
+                .filter(|(next_position, _)| position & 1 == 0 && *next_position == position ^ 1)
+                .map(|(_, sibling)| *sibling);
+            i += if sibling.is_some() { 2 } else { 1 };
+            parents.push((position >> 1, join(depth, position, value, sibling)?));
+        }
+        level = parents;
+        depth -= 1;
+    }
+}
+
+/// Checks that `leaf` is the leaf with global index `leaf_index` of the tree
+/// with the given root, holding `leaf_count` leaves.
+pub fn verify_inclusion(
+    root: &Hash,
+    leaf_index: u64,
+    leaf_count: u64,
+    leaf: &[u8],
+    proof: &MerkleProof,
+) -> bool {
+    proof.is_for_leaf(leaf_index, leaf_count) && proof.verify(root, hash_leaf(leaf))
+}
+
+/// Checks that `leaf` was inserted by `author` as the leaf with global index
+/// `leaf_index` of the tree with the given root, holding `leaf_count` leaves,
+/// either with `InsertAuthoredLeaf` or into an author bound tree.
+pub fn verify_authored_inclusion(
+    root: &Hash,
+    leaf_index: u64,
+    leaf_count: u64,
+    author: &Pubkey,
+    leaf: &[u8],
+    proof: &MerkleProof,
+) -> bool {
+    proof.is_for_leaf(leaf_index, leaf_count)
+        && proof.verify(root, hash_authored_leaf(author, leaf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtree::sub_tree::SUB_TREE_LEAFS;
+
+    #[test]
+    fn test_proof_across_sub_trees() {
+        let author = Pubkey::new_unique();
+        let mut child = SubTree::new();
+        child.insert_leaf(hash_leaf(b"moved"));
+        child.insert_leaf(hash_authored_leaf(&author, b"leaf"));
+
+        let mut root = SubTree::new();
+        for i in 0..SUB_TREE_LEAFS - 1 {
+            root.insert_leaf(hash_leaf(vec![i as u8]));
+        }
+        root.update_leaf(SUB_TREE_LEAFS - 1 + get_child_index(2), child.root_hash());
+
+        // Sub tree 2 holds its first inserted leaf, leaf 13 of 14.
+        let proof = MerkleProof::from_sub_trees(&[2, 0], &[child, root.clone()], 1).unwrap();
+        assert_eq!(proof.siblings.len(), 2 * SUB_TREE_DEPTH);
+        assert!(verify_authored_inclusion(
+            &root.root_hash(),
+            13,
+            14,
+            &author,
+            b"leaf",
+            &proof
+        ));
+        assert!(!verify_inclusion(
+            &root.root_hash(),
+            13,
+            14,
+            b"leaf",
+            &proof
+        ));
+        assert!(!verify_authored_inclusion(
+            &root.root_hash(),
+            13,
+            14,
+            &Pubkey::new_unique(),
+            b"leaf",
+            &proof
+        ));
+        assert!(!verify_authored_inclusion(
+            &root.root_hash(),
+            12,
+            14,
+            &author,
+            b"leaf",
+            &proof
+        ));
+
+        let proof = MerkleProof::from_sub_trees(&[0], &[root.clone()], 3).unwrap();
+        assert!(verify_inclusion(&root.root_hash(), 3, 14, &[3], &proof));
+        assert!(!verify_inclusion(&root.root_hash(), 3, 14, &[4], &proof));
+        assert!(!verify_inclusion(&root.root_hash(), 3, 3, &[3], &proof));
+    }
+
+    #[test]
+    fn test_inner_node_is_not_a_leaf() {
+        let mut tree = SubTree::new();
+        for i in 0..4u8 {
+            tree.insert_leaf(hash_leaf([i]));
+        }
+        let proof = MerkleProof::from_sub_trees(&[0], &[tree.clone()], 0).unwrap();
+
+        // The parent of leaves 0 and 1 hashes like a 64 byte leaf.
+        let inner = [hash_leaf([0]), hash_leaf([1])].concat();
+        let inner_proof = MerkleProof {
+            position: 0,
+            siblings: proof.siblings[1..].to_vec(),
+        };
+        assert!(inner_proof.verify(&tree.root_hash(), hash_leaf(&inner)));
+        assert!(!verify_inclusion(
+            &tree.root_hash(),
+            0,
+            4,
+            &inner,
+            &inner_proof
+        ));
+        assert!(verify_inclusion(&tree.root_hash(), 0, 4, &[0], &proof));
+    }
+
+    #[test]
+    fn test_multi_proof() {
+        let mut child = SubTree::new();
+        child.insert_leaf(hash_leaf([2]));
+        child.insert_leaf(hash_leaf([7]));
+        child.insert_leaf(hash_leaf([8]));
+
+        let mut root = SubTree::new();
+        for i in 0..SUB_TREE_LEAFS - 1 {
+            root.insert_leaf(hash_leaf([i as u8]));
+        }
+        root.update_leaf(SUB_TREE_LEAFS - 1 + get_child_index(3), child.root_hash());
+        let root_hash = root.root_hash();
+
+        let leaves = [
+            hash_leaf([8]),
+            hash_leaf([0]),
+            hash_leaf([1]),
+            hash_leaf([7]),
+        ];
+        let proofs = [
+            MerkleProof::from_sub_trees(&[3, 0], &[child.clone(), root.clone()], 2).unwrap(),
+            MerkleProof::from_sub_trees(&[0], &[root.clone()], 0).unwrap(),
+            MerkleProof::from_sub_trees(&[0], &[root.clone()], 1).unwrap(),
+            MerkleProof::from_sub_trees(&[3, 0], &[child, root], 1).unwrap(),
+        ];
+        let proof = MerkleMultiProof::from_proofs(&proofs).unwrap();
+        assert!(proof.verify(&root_hash, &leaves));
+        assert_eq!(
+            MultiProofLeaf::from_sub_tree_position(3, 2),
+            Some(proof.leaves[0])
+        );
+        assert_eq!(
+            MultiProofLeaf::from_sub_tree_position(0, 1),
+            Some(proof.leaves[2])
+        );
+        let single_siblings = proofs.iter().map(|p| p.siblings.len()).sum::<usize>();
+        assert!(proof.siblings.len() < single_siblings);
+
+        let mut swapped = leaves;
+        swapped.swap(1, 2);
+        assert!(!proof.verify(&root_hash, &swapped));
+        assert!(!proof.verify(&root_hash, &leaves[..3]));
+
+        let mut flipped = proof.clone();
+        flipped.flags[0] = !flipped.flags[0];
+        assert!(!flipped.verify(&root_hash, &leaves));
+
+        // The same leaf twice is accepted, at different places it is not.
+        let proof = MerkleMultiProof::from_proofs(&[proofs[1].clone(), proofs[1].clone()]).unwrap();
+        assert!(proof.verify(&root_hash, &[leaves[1], leaves[1]]));
+        assert!(!proof.verify(&root_hash, &[leaves[1], leaves[2]]));
+        assert!(MerkleMultiProof::from_proofs(&[]).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+use super::{join_hashes, Hash};
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::mem::size_of;
+use solana_program::{hash::hashv, pubkey::Pubkey};
+
+/// Number of hashing levels of a sparse tree, one per bit of the key.
+pub const SPARSE_TREE_DEPTH: usize = 256;
+
+/// Hash of an empty leaf and of every empty sub tree.
+pub const EMPTY_HASH: Hash = [0; 32];
+
+pub type SparseKey = [u8; 32];
+
+/// Hashes the leaf storing `value` at `key`: `H(key || value)`.
+pub fn hash_sparse_leaf(key: &SparseKey, value: &Hash) -> Hash {
+    hashv(&[key, value]).to_bytes()
+}
+
+/// Joins two nodes of a sparse tree. Two empty nodes give an empty node, so
+/// empty sub trees of any height hash to `EMPTY_HASH`.
+pub fn join_sparse_hashes(left: &Hash, right: &Hash) -> Hash {
+    if *left == EMPTY_HASH && *right == EMPTY_HASH {
+        EMPTY_HASH
+    } else {
+        join_hashes(left, right)
+    }
+}
+
+/// Returns the bit of `key` choosing the child at `depth`, `depth == 0`
+/// being the children of the root: `false` for left, `true` for right.
+fn key_bit(key: &SparseKey, depth: usize) -> bool {
+    key[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
+
+/// Compressed inclusion proof of a sparse tree leaf. Bit `level` of `bitmap`
+/// is set if the sibling at `level`, counted from the leaf, is not empty;
+/// only those siblings are stored, starting at the leaf.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SparseProof {
+    pub bitmap: [u8; 32],
+    pub siblings: Vec<Hash>,
+}
+
+impl SparseProof {
+    fn has_sibling(&self, level: usize) -> bool {
+        self.bitmap[level / 8] >> (level % 8) & 1 == 1
+    }
+
+    /// Computes the root implied by the proof for the leaf hash at `key`, or
+    /// `None` if the number of siblings does not match the bitmap.
+    pub fn root(&self, key: &SparseKey, leaf: Hash) -> Option<Hash> {
+        let mut siblings = self.siblings.iter();
+        let mut node = leaf;
+        for level in 0..SPARSE_TREE_DEPTH {
+            let sibling = if self.has_sibling(level) {
+                siblings.next()?
+            } else {
+                &EMPTY_HASH
+            };
+
+            node = if key_bit(key, SPARSE_TREE_DEPTH - 1 - level) {
+                join_sparse_hashes(sibling, &node)
+            } else {
+                join_sparse_hashes(&node, sibling)
+            };
+        }
+
+        if siblings.next().is_some() {
+            return None;
+        }
+        Some(node)
+    }
+
+    /// Checks that `key` holds `value` in the tree with the given root.
+    pub fn verify(&self, root: &Hash, key: &SparseKey, value: &Hash) -> bool {
+        self.root(key, hash_sparse_leaf(key, value)) == Some(*root)
+    }
+
+    /// Checks that `key` has no value in the tree with the given root.
+    pub fn verify_empty(&self, root: &Hash, key: &SparseKey) -> bool {
+        self.root(key, EMPTY_HASH) == Some(*root)
+    }
+}
+
+/// On-chain state of a sparse tree. Only the authority can change values.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default)]
+pub struct SparseTree {
+    pub authority: Pubkey,
+    pub root: Hash,
+    /// Number of keys holding a value, each listed by the slot account of an
+    /// index below it, so clients find every entry account without scanning
+    /// the program accounts.
+    pub key_count: u64,
+}
+
+impl SparseTree {
+    pub const LEN: usize = size_of::<Pubkey>() + size_of::<Hash>() + size_of::<u64>();
+}
+
+/// Account listing the key of a slot index. Deleting a key moves the key of
+/// the last slot into its slot, so slots stay contiguous.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SparseSlot {
+    pub key: SparseKey,
+}
+
+impl SparseSlot {
+    pub const LEN: usize = size_of::<SparseKey>();
+}
+
+/// Account holding the value of a key, so the tree can be rebuilt off-chain.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SparseEntry {
+    pub key: SparseKey,
+    pub value: Hash,
+}
+
+impl SparseEntry {
+    pub const LEN: usize = size_of::<SparseKey>() + size_of::<Hash>();
+}
+
+/// Off-chain copy of a sparse tree, built from all of its entries.
+pub struct SparseTreeEntries {
+    entries: Vec<SparseEntry>,
+}
+
+impl SparseTreeEntries {
+    pub fn new(mut entries: Vec<SparseEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.key);
+        entries.dedup_by(|a, b| a.key == b.key);
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[SparseEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, key: &SparseKey) -> Option<&Hash> {
+        self.entries
+            .binary_search_by(|entry| entry.key.cmp(key))
+            .ok()
+            .map(|index| &self.entries[index].value)
+    }
+
+    pub fn root(&self) -> Hash {
+        sub_tree_hash(&self.entries, 0)
+    }
+
+    /// Returns the proof of `key`, whether it holds a value or not.
+    pub fn get_proof(&self, key: &SparseKey) -> SparseProof {
+        let mut siblings = Vec::new();
+        let mut bitmap = [0; 32];
+        let mut entries = &self.entries[..];
+
+        for depth in 0..SPARSE_TREE_DEPTH {
+            let (left, right) = split(entries, depth);
+            let (path, other) = if key_bit(key, depth) {
+                (right, left)
+            } else {
+                (left, right)
+            };
+
+            let sibling = sub_tree_hash(other, depth + 1);
+            if sibling != EMPTY_HASH {
+                let level = SPARSE_TREE_DEPTH - 1 - depth;
+                bitmap[level / 8] |= 1 << (level % 8);
+                siblings.push(sibling);
+            }
+            entries = path;
+        }
+
+        siblings.reverse();
+        SparseProof { bitmap, siblings }
+    }
+}
+
+/// Splits sorted entries sharing the first `depth` key bits by the next bit.
+fn split(entries: &[SparseEntry], depth: usize) -> (&[SparseEntry], &[SparseEntry]) {
+    let index = entries.partition_point(|entry| !key_bit(&entry.key, depth));
+    entries.split_at(index)
+}
+
+/// Hashes the sub tree at `depth` holding the given entries.
+fn sub_tree_hash(entries: &[SparseEntry], depth: usize) -> Hash {
+    match entries {
+        [] => EMPTY_HASH,
+        [entry] if depth == SPARSE_TREE_DEPTH => hash_sparse_leaf(&entry.key, &entry.value),
+        _ => {
+            let (left, right) = split(entries, depth);
+            join_sparse_hashes(
+                &sub_tree_hash(left, depth + 1),
+                &sub_tree_hash(right, depth + 1),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key_byte: u8, value_byte: u8) -> SparseEntry {
+        SparseEntry {
+            key: [key_byte; 32],
+            value: [value_byte; 32],
+        }
+    }
+
+    #[test]
+    fn test_borsh_sparse_sizes() {
+        let tree = SparseTree::default();
+        assert_eq!(tree.try_to_vec().unwrap().len(), SparseTree::LEN);
+        let slot = SparseSlot::default();
+        assert_eq!(slot.try_to_vec().unwrap().len(), SparseSlot::LEN);
+        let entry = SparseEntry::default();
+        assert_eq!(entry.try_to_vec().unwrap().len(), SparseEntry::LEN);
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let tree = SparseTreeEntries::new(vec![]);
+        assert_eq!(tree.root(), EMPTY_HASH);
+
+        let proof = tree.get_proof(&[1; 32]);
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify_empty(&EMPTY_HASH, &[1; 32]));
+    }
+
+    #[test]
+    fn test_sparse_proofs() {
+        let entries = vec![entry(1, 10), entry(2, 20), entry(0x80, 30), entry(0x81, 40)];
+        let tree = SparseTreeEntries::new(entries.clone());
+        let root = tree.root();
+
+        for entry in &entries {
+            let proof = tree.get_proof(&entry.key);
+            assert!(proof.siblings.len() < 4);
+            assert!(proof.verify(&root, &entry.key, &entry.value));
+            assert!(!proof.verify(&root, &entry.key, &[0; 32]));
+            assert!(!proof.verify_empty(&root, &entry.key));
+        }
+
+        let absent = [3; 32];
+        let proof = tree.get_proof(&absent);
+        assert!(proof.verify_empty(&root, &absent));
+
+        // The proof of an empty key is also the proof needed to set it.
+        let new_root = proof
+            .root(&absent, hash_sparse_leaf(&absent, &[50; 32]))
+            .unwrap();
+        let mut updated = entries;
+        updated.push(entry(3, 50));
+        assert_eq!(SparseTreeEntries::new(updated).root(), new_root);
+
+        let mut proof = tree.get_proof(&[1; 32]);
+        proof.siblings.push([7; 32]);
+        assert_eq!(proof.root(&[1; 32], EMPTY_HASH), None);
+    }
+}
@@ -2,11 +2,13 @@ use super::{join_hashes, Hash};
 use borsh::{BorshDeserialize, BorshSerialize};
 
 pub const SUB_TREE_LEAFS: usize = 8;
+/// Number of hashing levels between the leaves and the root of a sub tree.
+pub const SUB_TREE_DEPTH: usize = SUB_TREE_LEAFS.trailing_zeros() as usize;
 
 const ELEMENTS_IN_SUB_TREE: usize = 2 * SUB_TREE_LEAFS - 1;
 pub const SUB_TREE_LEAF_SIZE: usize = SUB_TREE_SIZE / SUB_TREE_LEAFS;
 
-#[derive(BorshDeserialize, BorshSerialize, Debug)]
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct SubTree {
     nodes: Box<[Hash]>,
     next_index: u32,
@@ -38,6 +40,19 @@ impl SubTree {
         }
     }
 
+    /// Returns whether the sub tree has the expected shape and its internal
+    /// nodes match a recomputation from its leaves.
+    pub fn is_consistent(&self) -> bool {
+        if self.nodes.len() != ELEMENTS_IN_SUB_TREE
+            || !(SUB_TREE_LEAFS as u32 - 1..ELEMENTS_IN_SUB_TREE as u32).contains(&self.next_index)
+        {
+            return false;
+        }
+        let mut recomputed = self.clone();
+        recomputed.recompute();
+        recomputed.nodes == self.nodes
+    }
+
     pub fn is_full(&self) -> bool {
         self.next_index == ELEMENTS_IN_SUB_TREE as u32 - 1
     }
@@ -60,6 +75,12 @@ impl SubTree {
         self.next_index == SUB_TREE_LEAFS as u32 - 1
     }
 
+    /// Number of leaves written to the sub tree, including the leaf moved
+    /// down from its parent.
+    pub fn leaf_count(&self) -> usize {
+        self.next_index as usize + 1 - SUB_TREE_LEAFS
+    }
+
     pub fn insert_leaf(&mut self, leaf: Hash) -> bool {
         if self.is_full() {
             return false;
@@ -82,6 +103,22 @@ impl SubTree {
         }
     }
 
+    /// Returns the sibling hashes from the leaf at `index` up to the root.
+    pub fn get_proof(&self, index: usize) -> Option<Vec<Hash>> {
+        let mut i = index + SUB_TREE_LEAFS - 1;
+        if !(SUB_TREE_LEAFS - 1..ELEMENTS_IN_SUB_TREE).contains(&i) {
+            return None;
+        }
+
+        let mut proof = Vec::with_capacity(SUB_TREE_DEPTH);
+        while i > 0 {
+            let sibling = if i % 2 == 1 { i + 1 } else { i - 1 };
+            proof.push(self.nodes[sibling]);
+            i = (i - 1) / 2;
+        }
+        Some(proof)
+    }
+
     fn update_up(&mut self, index: usize) {
         let mut i = index;
         while i > 0 {
@@ -153,9 +190,11 @@ mod tests {
         let mut expected_tree = SubTree::new();
         let mut tree = SubTree::new();
         assert!(!tree.is_full());
+        assert_eq!(tree.leaf_count(), 0);
 
         for i in 0..SUB_TREE_LEAFS - 1 {
             assert!(tree.insert_leaf(idx_hash(i)));
+            assert_eq!(tree.leaf_count(), i + 1);
             expected_tree.nodes[expected_tree.next_index as usize] = idx_hash(i);
             expected_tree.next_index += 1;
             expected_tree.recompute();
@@ -186,4 +225,44 @@ mod tests {
         assert!(tree.update_leaf(update_idx, idx_hash(88)));
         assert_ne!(before_update, tree.root_hash());
     }
+
+    #[test]
+    fn test_is_consistent() {
+        let mut tree = SubTree::new();
+        assert!(tree.is_consistent());
+        tree.insert_leaf(idx_hash(1));
+        assert!(tree.is_consistent());
+
+        let mut corrupted = tree.clone();
+        corrupted.nodes[1] = idx_hash(2);
+        assert!(!corrupted.is_consistent());
+
+        let mut corrupted = tree.clone();
+        corrupted.nodes = corrupted.nodes[1..].into();
+        assert!(!corrupted.is_consistent());
+    }
+
+    #[test]
+    fn test_get_proof() {
+        let mut tree = SubTree::new();
+        for i in 0..SUB_TREE_LEAFS - 1 {
+            tree.insert_leaf(idx_hash(i));
+        }
+
+        for i in 0..SUB_TREE_LEAFS {
+            let proof = tree.get_proof(i).unwrap();
+            assert_eq!(proof.len(), SUB_TREE_DEPTH);
+
+            let mut hash = tree.get_leaf(i).unwrap();
+            for (level, sibling) in proof.iter().enumerate() {
+                hash = if (i >> level) & 1 == 0 {
+                    join_hashes(&hash, sibling)
+                } else {
+                    join_hashes(sibling, &hash)
+                };
+            }
+            assert_eq!(hash, tree.root_hash());
+        }
+        assert!(tree.get_proof(SUB_TREE_LEAFS).is_none());
+    }
 }
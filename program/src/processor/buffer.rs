@@ -0,0 +1,180 @@
+use super::{append_leaf, next_insert_accounts, NewLeaf};
+use crate::{
+    assertions::{assert_signer, assert_system_program},
+    buffer::LeafBuffer,
+    error::MtreeError,
+    info::{find_buffer_pda, BUFFER_SEED},
+};
+use borsh::{BorshDeserialize as _, BorshSerialize as _};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar as _,
+};
+
+pub fn init_buffer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    nonce: u64,
+    data_len: u32,
+    bind_author: bool,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let authority = next_account_info(accounts_iterator)?;
+    assert_signer("authority", authority)?;
+
+    let buffer_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    let (buffer_key, bump) = find_buffer_pda(authority.key, nonce, program_id);
+    if *buffer_acc.key != buffer_key {
+        return Err(MtreeError::InvalidBufferAccount.into());
+    }
+    if !buffer_acc.data_is_empty() {
+        return Err(MtreeError::BufferAlreadyInitialized.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            buffer_acc.key,
+            rent.minimum_balance(LeafBuffer::LEN),
+            LeafBuffer::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), buffer_acc.clone(), sys.clone()],
+        &[&[
+            BUFFER_SEED,
+            authority.key.as_ref(),
+            &nonce.to_be_bytes(),
+            &[bump],
+        ]],
+    )?;
+
+    LeafBuffer::new(*authority.key, nonce, bump, data_len, bind_author)
+        .serialize(&mut *buffer_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn write_buffer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    offset: u32,
+    data: Vec<u8>,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let authority = next_account_info(accounts_iterator)?;
+    assert_signer("authority", authority)?;
+
+    let buffer_acc = next_account_info(accounts_iterator)?;
+    let mut buffer = load_buffer(buffer_acc, authority.key, program_id)?;
+
+    if offset != buffer.written {
+        return Err(MtreeError::BufferWriteOutOfOrder.into());
+    }
+    buffer.written = u32::try_from(data.len())
+        .ok()
+        .and_then(|len| buffer.written.checked_add(len))
+        .filter(|end| *end <= buffer.data_len)
+        .ok_or(MtreeError::BufferWriteOutOfBounds)?;
+    buffer.hasher.update(&data);
+
+    buffer.serialize(&mut *buffer_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn insert_from_buffer(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let insert_accounts = next_insert_accounts(accounts_iterator)?;
+    let payer = insert_accounts.payer;
+
+    let buffer_acc = next_account_info(accounts_iterator)?;
+    let buffer = load_buffer(buffer_acc, insert_accounts.author.key, program_id)?;
+    if !buffer.is_complete() {
+        return Err(MtreeError::IncompleteBuffer.into());
+    }
+
+    let leaf = NewLeaf::Hashed {
+        len: buffer.data_len as usize,
+        hash: buffer.hasher.finalize(),
+        bind_author: buffer.bind_author,
+    };
+    append_leaf(program_id, insert_accounts, accounts_iterator, leaf)?;
+
+    close_buffer_account(buffer_acc, payer)
+}
+
+pub fn close_buffer(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let authority = next_account_info(accounts_iterator)?;
+    assert_signer("authority", authority)?;
+
+    let buffer_acc = next_account_info(accounts_iterator)?;
+    load_buffer(buffer_acc, authority.key, program_id)?;
+
+    let receiver = next_account_info(accounts_iterator)?;
+    close_buffer_account(buffer_acc, receiver)
+}
+
+/// Loads a buffer created by `init_buffer`. The key is checked against the
+/// PDA of the stored authority and nonce, so no other account of the
+/// program passes for a buffer.
+fn load_buffer(
+    buffer_acc: &AccountInfo,
+    authority: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<LeafBuffer, ProgramError> {
+    if buffer_acc.owner != program_id {
+        return Err(MtreeError::InvalidBufferAccount.into());
+    }
+    if buffer_acc.data_is_empty() {
+        return Err(MtreeError::UninitializedBuffer.into());
+    }
+
+    let buffer = LeafBuffer::try_from_slice(&buffer_acc.try_borrow_data()?)
+        .map_err(|_| MtreeError::InvalidBufferAccount)?;
+    let buffer_key = Pubkey::create_program_address(
+        &[
+            BUFFER_SEED,
+            buffer.authority.as_ref(),
+            &buffer.nonce.to_be_bytes(),
+            &[buffer.bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| MtreeError::InvalidBufferAccount)?;
+    if *buffer_acc.key != buffer_key {
+        return Err(MtreeError::InvalidBufferAccount.into());
+    }
+    if buffer.authority != *authority {
+        return Err(MtreeError::InvalidBufferAuthority.into());
+    }
+
+    Ok(buffer)
+}
+
+fn close_buffer_account<'a>(
+    buffer_acc: &AccountInfo<'a>,
+    receiver: &AccountInfo<'a>,
+) -> ProgramResult {
+    let lamports = buffer_acc.lamports();
+    **buffer_acc.try_borrow_mut_lamports()? -= lamports;
+    **receiver.try_borrow_mut_lamports()? += lamports;
+
+    buffer_acc.realloc(0, false)?;
+    buffer_acc.assign(&system_program::ID);
+    Ok(())
+}
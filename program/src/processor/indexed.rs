@@ -0,0 +1,146 @@
+use crate::{
+    assertions::{assert_signer, assert_system_program},
+    error::MtreeError,
+    info::{find_indexed_leaf_pda, find_indexed_tree_pda, INDEXED_LEAF_SEED, INDEXED_TREE_SEED},
+    mtree::{
+        indexed::{IndexedLeaf, IndexedTree},
+        Hash,
+    },
+};
+use borsh::{BorshDeserialize as _, BorshSerialize as _};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar as _,
+};
+
+pub fn init_indexed_tree(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let tree_acc = next_account_info(accounts_iterator)?;
+    let leaf_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    let tree_key = find_indexed_tree_pda(program_id);
+    if *tree_acc.key != tree_key.0 {
+        return Err(MtreeError::InvalidIndexedTreeAccount.into());
+    }
+    if !tree_acc.data_is_empty() {
+        return Err(MtreeError::TreeAlreadyInitialized.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            tree_acc.key,
+            rent.minimum_balance(IndexedTree::LEN),
+            IndexedTree::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), tree_acc.clone(), sys.clone()],
+        &[&[INDEXED_TREE_SEED, &[tree_key.1]]],
+    )?;
+    create_indexed_leaf(payer, leaf_acc, sys, program_id, &rent, 0)?;
+
+    IndexedTree::new().serialize(&mut *tree_acc.try_borrow_mut_data()?)?;
+    IndexedLeaf::default().serialize(&mut *leaf_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn insert_indexed(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    value: Hash,
+    low_leaf_index: u64,
+    low_leaf_proof: Vec<Hash>,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let tree_acc = next_account_info(accounts_iterator)?;
+    let low_leaf_acc = next_account_info(accounts_iterator)?;
+    let new_leaf_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    let mut tree = load_indexed_tree(tree_acc, program_id)?;
+    let low_leaf = load_indexed_leaf(low_leaf_acc, low_leaf_index, program_id)?;
+    let new_leaf_index = tree.next_index;
+
+    let (low_leaf, new_leaf) = tree.insert(value, &low_leaf, low_leaf_index, &low_leaf_proof)?;
+
+    let rent = Rent::get()?;
+    create_indexed_leaf(payer, new_leaf_acc, sys, program_id, &rent, new_leaf_index)?;
+
+    low_leaf.serialize(&mut *low_leaf_acc.try_borrow_mut_data()?)?;
+    new_leaf.serialize(&mut *new_leaf_acc.try_borrow_mut_data()?)?;
+    tree.serialize(&mut *tree_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+fn load_indexed_tree(
+    tree_acc: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<IndexedTree, ProgramError> {
+    if *tree_acc.key != find_indexed_tree_pda(program_id).0 {
+        return Err(MtreeError::InvalidIndexedTreeAccount.into());
+    }
+
+    if tree_acc.data_is_empty() {
+        return Err(MtreeError::UninitializedTree.into());
+    }
+
+    let data = tree_acc.try_borrow_data()?;
+    IndexedTree::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+fn load_indexed_leaf(
+    leaf_acc: &AccountInfo,
+    index: u64,
+    program_id: &Pubkey,
+) -> Result<IndexedLeaf, ProgramError> {
+    if *leaf_acc.key != find_indexed_leaf_pda(index, program_id).0 || leaf_acc.data_is_empty() {
+        return Err(MtreeError::InvalidIndexedLeafAccount.into());
+    }
+
+    let data = leaf_acc.try_borrow_data()?;
+    IndexedLeaf::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+fn create_indexed_leaf<'a>(
+    payer: &AccountInfo<'a>,
+    leaf_acc: &AccountInfo<'a>,
+    sys: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    rent: &Rent,
+    index: u64,
+) -> ProgramResult {
+    let leaf_key = find_indexed_leaf_pda(index, program_id);
+    if *leaf_acc.key != leaf_key.0 {
+        return Err(MtreeError::InvalidIndexedLeafAccount.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            leaf_acc.key,
+            rent.minimum_balance(IndexedLeaf::LEN),
+            IndexedLeaf::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), leaf_acc.clone(), sys.clone()],
+        &[&[INDEXED_LEAF_SEED, &index.to_be_bytes(), &[leaf_key.1]]],
+    )
+}
@@ -0,0 +1,102 @@
+use super::{get_or_init_info, hash_new_leaf, next_insert_accounts, InsertAccounts, NewLeaf};
+use crate::{
+    error::MtreeError,
+    events::MTreeEvent,
+    info::{find_mmr_page_pda, MMR_PAGE_SEED},
+    mtree::{
+        mmr::{MMR_PAGE_LEAVES, MMR_PAGE_SIZE},
+        Hash,
+    },
+    return_data::InsertLeafReturn,
+};
+use borsh::BorshSerialize as _;
+use core::mem::size_of;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar as _,
+};
+
+pub fn append_mmr_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf: Vec<u8>,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let InsertAccounts {
+        payer,
+        author,
+        info: info_acc,
+        sys,
+    } = next_insert_accounts(accounts_iterator)?;
+    let page_acc = next_account_info(accounts_iterator)?;
+
+    let rent = Rent::get()?;
+    let (mut info, _) = get_or_init_info(info_acc, payer, sys, program_id, &rent)?;
+
+    let leaf = NewLeaf::Data {
+        data: &leaf,
+        bind_author: false,
+    };
+    let leaf_hash = hash_new_leaf(&info.config, author.key, leaf)?;
+
+    let leaf_index = info.mmr.leaf_count;
+    info.mmr.append(leaf_hash)?;
+    write_mmr_page(
+        page_acc, payer, sys, program_id, &rent, leaf_index, &leaf_hash,
+    )?;
+    info.serialize(&mut *info_acc.try_borrow_mut_data()?)?;
+
+    MTreeEvent::MmrLeafAppended {
+        leaf_index,
+        leaf_hash,
+    }
+    .send()
+    .map_err(|_| MtreeError::FailedToSendEvent)?;
+    InsertLeafReturn {
+        leaf_index,
+        leaf_hash,
+        root_hash: info.mmr.root,
+    }
+    .set()
+}
+
+/// Stores the leaf hash in the page account of its index, creating the page
+/// for its first leaf.
+fn write_mmr_page<'a>(
+    page_acc: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    sys: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    rent: &Rent,
+    leaf_index: u64,
+    leaf_hash: &Hash,
+) -> ProgramResult {
+    let page = leaf_index / MMR_PAGE_LEAVES;
+    let page_key = find_mmr_page_pda(page, program_id);
+    if *page_acc.key != page_key.0 {
+        return Err(MtreeError::InvalidMmrPageAccount.into());
+    }
+
+    if page_acc.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                page_acc.key,
+                rent.minimum_balance(MMR_PAGE_SIZE),
+                MMR_PAGE_SIZE as u64,
+                program_id,
+            ),
+            &[payer.clone(), page_acc.clone(), sys.clone()],
+            &[&[MMR_PAGE_SEED, &page.to_be_bytes(), &[page_key.1]]],
+        )?;
+    }
+
+    let offset = (leaf_index % MMR_PAGE_LEAVES) as usize * size_of::<Hash>();
+    page_acc.try_borrow_mut_data()?[offset..offset + size_of::<Hash>()].copy_from_slice(leaf_hash);
+    Ok(())
+}
@@ -0,0 +1,586 @@
+use crate::{
+    assertions::{
+        assert_ed25519_signature, assert_signer, assert_system_program, assert_upgrade_authority,
+    },
+    error::MtreeError,
+    events::MTreeEvent,
+    info::{
+        find_info_pda, find_leaf_marker_pda, find_signed_leaf_pda, find_sub_tree_pda,
+        signed_leaf_message, signed_leaf_message_hash, LeafMarker, LeafMode, LegacyMTreeInfo,
+        MTreeInfo, SignedLeafMarker, TreeConfig, INFO_SEED, INFO_VERSION, LEAF_MARKER_SEED,
+        SIGNED_LEAF_SEED,
+    },
+    mtree::{
+        hash_authored_leaf, hash_leaf,
+        path::{get_child_index, get_first_leaf_index, get_path_to_root},
+        proof::MerkleMultiProof,
+        sub_tree::{SubTree, SUB_TREE_LEAFS, SUB_TREE_LEAF_SIZE, SUB_TREE_SIZE},
+        Hash, SubTreeId,
+    },
+    return_data::InsertLeafReturn,
+};
+use borsh::{BorshDeserialize as _, BorshSerialize as _};
+use core::slice::Iter;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar as _,
+};
+
+mod buffer;
+mod indexed;
+mod mmr;
+mod sparse;
+
+pub use buffer::{close_buffer, init_buffer, insert_from_buffer, write_buffer};
+pub use indexed::{init_indexed_tree, insert_indexed};
+pub use mmr::append_mmr_leaf;
+pub use sparse::{delete_sparse_value, init_sparse_tree, set_sparse_value};
+
+pub fn init_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: TreeConfig,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let info_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    let upgrade_authority = next_account_info(accounts_iterator)?;
+    let program_data = next_account_info(accounts_iterator)?;
+    assert_upgrade_authority(program_id, program_data, upgrade_authority)?;
+
+    if *info_acc.key != find_info_pda(program_id).0 {
+        return Err(MtreeError::InvalidInfoAccount.into());
+    }
+    if !info_acc.data_is_empty() {
+        return Err(MtreeError::TreeAlreadyInitialized.into());
+    }
+    config.leaf_policy.validate()?;
+
+    let rent = Rent::get()?;
+    let (info, _) = get_or_init_info(info_acc, payer, sys, program_id, &rent)?;
+    MTreeInfo { config, ..info }.serialize(&mut *info_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn migrate_info(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let info_acc = next_account_info(accounts_iterator)?;
+    let sub_tree_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    if *info_acc.key != find_info_pda(program_id).0 {
+        return Err(MtreeError::InvalidInfoAccount.into());
+    }
+    if info_acc.data_is_empty() {
+        return Err(MtreeError::UninitializedTree.into());
+    }
+    if info_acc.data_len() != LegacyMTreeInfo::LEN {
+        return Err(MtreeError::InfoAlreadyMigrated.into());
+    }
+    let legacy = LegacyMTreeInfo::try_from_slice(&info_acc.try_borrow_data()?)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    if *sub_tree_acc.key != find_sub_tree_pda(legacy.tree_id, program_id).0 {
+        return Err(MtreeError::InvalidNodeAccount.into());
+    }
+    // Every sub tree but the root also holds the leaf moved down from its
+    // parent, which is counted in an earlier sub tree.
+    let mut leaf_count = get_first_leaf_index(legacy.tree_id);
+    if !sub_tree_acc.data_is_empty() {
+        let sub_tree = SubTree::try_from_slice(&sub_tree_acc.try_borrow_data()?)
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        leaf_count += (sub_tree.leaf_count() as u64).saturating_sub(u64::from(legacy.tree_id != 0));
+    }
+
+    let rent = Rent::get()?;
+    let top_up = rent
+        .minimum_balance(MTreeInfo::LEN)
+        .saturating_sub(rent.minimum_balance(LegacyMTreeInfo::LEN));
+    invoke_signed(
+        &system_instruction::transfer(payer.key, info_acc.key, top_up),
+        &[payer.clone(), info_acc.clone()],
+        &[],
+    )?;
+    info_acc.realloc(MTreeInfo::LEN, true)?;
+
+    MTreeInfo {
+        tree_id: legacy.tree_id,
+        root_hash: legacy.root_hash,
+        leaf_count,
+        ..Default::default()
+    }
+    .serialize(&mut *info_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn insert_leaf(program_id: &Pubkey, accounts: &[AccountInfo], leaf: Vec<u8>) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let insert_accounts = next_insert_accounts(accounts_iterator)?;
+    let leaf = NewLeaf::Data {
+        data: &leaf,
+        bind_author: false,
+    };
+    append_leaf(program_id, insert_accounts, accounts_iterator, leaf)
+}
+
+pub fn insert_authored_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf: Vec<u8>,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let insert_accounts = next_insert_accounts(accounts_iterator)?;
+    let leaf = NewLeaf::Data {
+        data: &leaf,
+        bind_author: true,
+    };
+    append_leaf(program_id, insert_accounts, accounts_iterator, leaf)
+}
+
+pub fn insert_signed_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    nonce: u64,
+    leaf: Vec<u8>,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let insert_accounts = next_insert_accounts(accounts_iterator)?;
+    let instructions_sysvar = next_account_info(accounts_iterator)?;
+    let replay_marker = next_account_info(accounts_iterator)?;
+
+    let info = load_info(insert_accounts.info, program_id)?;
+    if info.config.leaf_signer == Pubkey::default() {
+        return Err(MtreeError::LeafSignerNotConfigured.into());
+    }
+    let message = signed_leaf_message(insert_accounts.info.key, nonce, &leaf);
+    assert_ed25519_signature(instructions_sysvar, &info.config.leaf_signer, &message)?;
+    create_signed_leaf_marker(
+        insert_accounts.payer,
+        replay_marker,
+        insert_accounts.sys,
+        program_id,
+        &signed_leaf_message_hash(&message),
+    )?;
+
+    let leaf = NewLeaf::Data {
+        data: &leaf,
+        bind_author: false,
+    };
+    append_leaf(program_id, insert_accounts, accounts_iterator, leaf)
+}
+
+pub fn verify_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_indexes: Vec<u64>,
+    leaves: Vec<Hash>,
+    proof: MerkleMultiProof,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let info_acc = next_account_info(accounts_iterator)?;
+
+    let info = load_info(info_acc, program_id)?;
+    let pinned = leaf_indexes.len() == proof.leaves.len()
+        && leaf_indexes
+            .iter()
+            .zip(&proof.leaves)
+            .all(|(leaf_index, leaf)| info.multi_proof_leaf(*leaf_index) == Some(*leaf));
+    if !pinned || !proof.verify(&info.root_hash, &leaves) {
+        return Err(MtreeError::InvalidProof.into());
+    }
+    Ok(())
+}
+
+struct InsertAccounts<'a, 'b> {
+    payer: &'a AccountInfo<'b>,
+    author: &'a AccountInfo<'b>,
+    info: &'a AccountInfo<'b>,
+    sys: &'a AccountInfo<'b>,
+}
+
+fn next_insert_accounts<'a, 'b>(
+    accounts_iterator: &mut Iter<'a, AccountInfo<'b>>,
+) -> Result<InsertAccounts<'a, 'b>, ProgramError> {
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let author = next_account_info(accounts_iterator)?;
+    assert_signer("author", author)?;
+
+    let info = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    Ok(InsertAccounts {
+        payer,
+        author,
+        info,
+        sys,
+    })
+}
+
+/// Leaf appended by `append_leaf`.
+enum NewLeaf<'l> {
+    /// Leaf data, hashed with the author if `bind_author` is set or the tree
+    /// is author bound.
+    Data { data: &'l [u8], bind_author: bool },
+    /// Leaf hashed while it was staged in a buffer.
+    Hashed {
+        len: usize,
+        hash: Hash,
+        bind_author: bool,
+    },
+}
+
+fn append_leaf<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: InsertAccounts<'a, 'b>,
+    accounts_iterator: &mut Iter<'a, AccountInfo<'b>>,
+    leaf: NewLeaf,
+) -> ProgramResult {
+    let InsertAccounts {
+        payer,
+        author,
+        info: info_acc,
+        sys,
+    } = accounts;
+
+    let rent = Rent::get()?;
+    let (mut info, _) = get_or_init_info(info_acc, payer, sys, program_id, &rent)?;
+
+    let leaf_hash = hash_new_leaf(&info.config, author.key, leaf)?;
+
+    if info.root_hash != Hash::default() {
+        transfer_commission(info_acc, payer, &rent)?;
+    }
+
+    // The remaining accounts are looked up by key, so a client may pass the
+    // sub trees of both the current and the next path and still land its
+    // insert when another one fills the current sub tree first.
+    let remaining = accounts_iterator.as_slice();
+
+    let path = get_path_to_root(info.tree_id);
+    let last_sub_tree_id = path[0];
+
+    let (last_node_acc, mut leaf_sub_tree) = get_or_init_sub_tree(
+        payer,
+        info_acc,
+        remaining,
+        last_sub_tree_id,
+        sys,
+        program_id,
+        &rent,
+    )?;
+
+    if leaf_sub_tree.is_full() {
+        return Err(MtreeError::SubTreeFull.into());
+    }
+
+    let mut parents = Vec::with_capacity(path.len() - 1);
+    for tree_id in path.iter().skip(1) {
+        parents.push(load_sub_tree(remaining, *tree_id, program_id)?);
+    }
+
+    // A new sub tree takes the place of a leaf of its parent, so the leaf is
+    // moved down into the first position of the new sub tree.
+    if leaf_sub_tree.is_empty() {
+        if let Some((_, parent)) = parents.first() {
+            if let Some(leaf) = parent.get_leaf(get_child_index(last_sub_tree_id)) {
+                leaf_sub_tree.insert_leaf(leaf);
+            }
+        }
+    }
+
+    if info.config.dedup {
+        create_leaf_marker(
+            payer,
+            remaining,
+            sys,
+            program_id,
+            &rent,
+            &leaf_hash,
+            info.leaf_count,
+        )?;
+    }
+    leaf_sub_tree.insert_leaf(leaf_hash);
+
+    if leaf_sub_tree.is_full() {
+        info.tree_id = last_sub_tree_id + 1;
+    }
+
+    let mut root_hash = leaf_sub_tree.root_hash();
+    let mut child_id = last_sub_tree_id;
+
+    for ((sub_tree_acc, mut sub_tree), tree_id) in parents.into_iter().zip(path.iter().skip(1)) {
+        let child_index = get_child_index(child_id);
+        sub_tree.update_leaf(SUB_TREE_LEAFS - 1 + child_index, root_hash);
+        sub_tree.serialize(&mut *sub_tree_acc.try_borrow_mut_data()?)?;
+        root_hash = sub_tree.root_hash();
+        child_id = *tree_id;
+    }
+
+    leaf_sub_tree.serialize(&mut *last_node_acc.try_borrow_mut_data()?)?;
+
+    info.root_hash = root_hash;
+    info.leaf_count += 1;
+    info.serialize(&mut *info_acc.try_borrow_mut_data()?)?;
+
+    MTreeEvent::NewRootHash(info.root_hash)
+        .send()
+        .map_err(|_| MtreeError::FailedToSendEvent)?;
+    InsertLeafReturn {
+        leaf_index: info.leaf_count - 1,
+        leaf_hash,
+        root_hash: info.root_hash,
+    }
+    .set()
+}
+
+/// Checks the leaf against the policy of the tree and returns its hash,
+/// bound to `author` if the leaf asks for it or the tree is author bound.
+fn hash_new_leaf(config: &TreeConfig, author: &Pubkey, leaf: NewLeaf) -> Result<Hash, MtreeError> {
+    let leaf_len = match leaf {
+        NewLeaf::Data { data, .. } => data.len(),
+        NewLeaf::Hashed { len, .. } => len,
+    };
+    config.leaf_policy.check(leaf_len)?;
+
+    let author_bound = config.leaf_mode == LeafMode::AuthorBound;
+    match leaf {
+        NewLeaf::Data { data, bind_author } if bind_author || author_bound => {
+            Ok(hash_authored_leaf(author, data))
+        }
+        NewLeaf::Data { data, .. } => Ok(hash_leaf(data)),
+        NewLeaf::Hashed {
+            bind_author: false, ..
+        } if author_bound => Err(MtreeError::BufferNotAuthorBound),
+        NewLeaf::Hashed { hash, .. } => Ok(hash),
+    }
+}
+
+fn load_info(info_acc: &AccountInfo, program_id: &Pubkey) -> Result<MTreeInfo, ProgramError> {
+    if *info_acc.key != find_info_pda(program_id).0 {
+        return Err(MtreeError::InvalidInfoAccount.into());
+    }
+
+    if info_acc.data_is_empty() {
+        return Err(MtreeError::UninitializedTree.into());
+    }
+
+    read_info(info_acc)
+}
+
+/// Deserializes an initialized info account, which must be in the current
+/// layout.
+fn read_info(info_acc: &AccountInfo) -> Result<MTreeInfo, ProgramError> {
+    let data = info_acc.try_borrow_data()?;
+    if data.len() == LegacyMTreeInfo::LEN {
+        return Err(MtreeError::InfoNotMigrated.into());
+    }
+
+    let info =
+        MTreeInfo::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)?;
+    if info.version != INFO_VERSION {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(info)
+}
+
+fn find_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Option<&'a AccountInfo<'b>> {
+    accounts.iter().find(|account| account.key == key)
+}
+
+fn load_sub_tree<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    id: SubTreeId,
+    program_id: &Pubkey,
+) -> Result<(&'a AccountInfo<'b>, SubTree), ProgramError> {
+    let node_key = find_sub_tree_pda(id, program_id);
+    let sub_tree_acc = find_account(accounts, &node_key.0).ok_or(MtreeError::InvalidNodeAccount)?;
+
+    if sub_tree_acc.data_is_empty() {
+        return Err(MtreeError::UninitializedSubTree.into());
+    }
+
+    let data = sub_tree_acc.try_borrow_data()?;
+    let sub_tree =
+        SubTree::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)?;
+    Ok((sub_tree_acc, sub_tree))
+}
+
+fn get_or_init_sub_tree<'a, 'b>(
+    payer: &AccountInfo<'b>,
+    info_acc: &AccountInfo<'b>,
+    accounts: &'a [AccountInfo<'b>],
+    id: SubTreeId,
+    sys: &AccountInfo<'b>,
+    program_id: &Pubkey,
+    rent: &Rent,
+) -> Result<(&'a AccountInfo<'b>, SubTree), ProgramError> {
+    let node_key = find_sub_tree_pda(id, program_id);
+    let sub_tree_acc = find_account(accounts, &node_key.0).ok_or(MtreeError::InvalidNodeAccount)?;
+
+    if !sub_tree_acc.data_is_empty() {
+        let data = sub_tree_acc.try_borrow_data()?;
+        let sub_tree =
+            SubTree::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)?;
+        return Ok((sub_tree_acc, sub_tree));
+    }
+
+    let rent = rent.minimum_balance(SUB_TREE_SIZE);
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            sub_tree_acc.key,
+            1,
+            SUB_TREE_SIZE as u64,
+            program_id,
+        ),
+        &[payer.clone(), sub_tree_acc.clone(), sys.clone()],
+        &[&[&id.to_be_bytes()[..], &[node_key.1]]],
+    )?;
+    **info_acc.try_borrow_mut_lamports()? -= rent;
+    **sub_tree_acc.try_borrow_mut_lamports()? += rent;
+
+    Ok((sub_tree_acc, SubTree::default()))
+}
+
+fn create_leaf_marker<'a>(
+    payer: &AccountInfo<'a>,
+    accounts: &[AccountInfo<'a>],
+    sys: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    rent: &Rent,
+    leaf_hash: &Hash,
+    leaf_index: u64,
+) -> ProgramResult {
+    let marker_key = find_leaf_marker_pda(leaf_hash, program_id);
+    let marker_acc =
+        find_account(accounts, &marker_key.0).ok_or(MtreeError::InvalidLeafMarkerAccount)?;
+
+    if !marker_acc.data_is_empty() {
+        return Err(MtreeError::DuplicateLeaf.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            marker_acc.key,
+            rent.minimum_balance(LeafMarker::LEN),
+            LeafMarker::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), marker_acc.clone(), sys.clone()],
+        &[&[LEAF_MARKER_SEED, leaf_hash, &[marker_key.1]]],
+    )?;
+
+    LeafMarker {
+        leaf_index,
+        slot: Clock::get()?.slot,
+    }
+    .serialize(&mut *marker_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+/// Creates the replay marker of a signed leaf message, failing if the message
+/// was already inserted.
+fn create_signed_leaf_marker<'a>(
+    payer: &AccountInfo<'a>,
+    marker_acc: &AccountInfo<'a>,
+    sys: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    message_hash: &Hash,
+) -> ProgramResult {
+    let marker_key = find_signed_leaf_pda(message_hash, program_id);
+    if *marker_acc.key != marker_key.0 {
+        return Err(MtreeError::InvalidSignedLeafMarkerAccount.into());
+    }
+    if !marker_acc.data_is_empty() {
+        return Err(MtreeError::SignedLeafReplayed.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            marker_acc.key,
+            Rent::get()?.minimum_balance(SignedLeafMarker::LEN),
+            SignedLeafMarker::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), marker_acc.clone(), sys.clone()],
+        &[&[SIGNED_LEAF_SEED, message_hash, &[marker_key.1]]],
+    )?;
+
+    SignedLeafMarker {
+        slot: Clock::get()?.slot,
+    }
+    .serialize(&mut *marker_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+fn transfer_commission<'a>(
+    info_acc: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    rent: &Rent,
+) -> ProgramResult {
+    let commission = rent.minimum_balance(SUB_TREE_LEAF_SIZE);
+    invoke_signed(
+        &system_instruction::transfer(payer.key, info_acc.key, commission),
+        &[payer.clone(), info_acc.clone()],
+        &[],
+    )
+}
+
+fn get_or_init_info<'a>(
+    info: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    sys: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    rent: &Rent,
+) -> Result<(MTreeInfo, u8), ProgramError> {
+    let info_key = find_info_pda(program_id);
+    if *info.key != info_key.0 {
+        return Err(MtreeError::InvalidInfoAccount.into());
+    }
+
+    if !info.data_is_empty() {
+        return Ok((read_info(info)?, info_key.1));
+    }
+
+    let lamports = rent.minimum_balance(MTreeInfo::LEN) + rent.minimum_balance(SUB_TREE_SIZE);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            info.key,
+            lamports,
+            MTreeInfo::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), info.clone(), sys.clone()],
+        &[&[INFO_SEED, &[info_key.1]]],
+    )?;
+
+    Ok((MTreeInfo::default(), info_key.1))
+}
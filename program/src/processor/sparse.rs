@@ -0,0 +1,275 @@
+use crate::{
+    assertions::{assert_signer, assert_system_program},
+    error::MtreeError,
+    info::{
+        find_sparse_entry_pda, find_sparse_slot_pda, find_sparse_tree_pda, SPARSE_ENTRY_SEED,
+        SPARSE_SLOT_SEED, SPARSE_TREE_SEED,
+    },
+    mtree::{
+        sparse::{
+            hash_sparse_leaf, SparseEntry, SparseKey, SparseProof, SparseSlot, SparseTree,
+            EMPTY_HASH,
+        },
+        Hash,
+    },
+};
+use borsh::{BorshDeserialize as _, BorshSerialize as _};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar as _,
+};
+
+pub fn init_sparse_tree(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let authority = next_account_info(accounts_iterator)?;
+    assert_signer("authority", authority)?;
+
+    let tree_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    let tree_key = find_sparse_tree_pda(program_id);
+    if *tree_acc.key != tree_key.0 {
+        return Err(MtreeError::InvalidInfoAccount.into());
+    }
+    if !tree_acc.data_is_empty() {
+        return Err(MtreeError::TreeAlreadyInitialized.into());
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            tree_acc.key,
+            rent.minimum_balance(SparseTree::LEN),
+            SparseTree::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), tree_acc.clone(), sys.clone()],
+        &[&[SPARSE_TREE_SEED, &[tree_key.1]]],
+    )?;
+
+    SparseTree {
+        authority: *authority.key,
+        root: EMPTY_HASH,
+        key_count: 0,
+    }
+    .serialize(&mut *tree_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn set_sparse_value(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    key: SparseKey,
+    value: Hash,
+    proof: SparseProof,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let payer = next_account_info(accounts_iterator)?;
+    assert_signer("payer", payer)?;
+
+    let authority = next_account_info(accounts_iterator)?;
+    let tree_acc = next_account_info(accounts_iterator)?;
+    let entry_acc = next_account_info(accounts_iterator)?;
+    let slot_acc = next_account_info(accounts_iterator)?;
+    let sys = next_account_info(accounts_iterator)?;
+    assert_system_program(sys)?;
+
+    let mut tree = load_sparse_tree(tree_acc, authority, program_id)?;
+    let entry_key = find_sparse_entry_pda(&key, program_id);
+    if *entry_acc.key != entry_key.0 {
+        return Err(MtreeError::InvalidSparseEntryAccount.into());
+    }
+
+    let old_leaf = if entry_acc.data_is_empty() {
+        let rent = Rent::get()?;
+        create_sparse_slot(slot_acc, payer, sys, program_id, &rent, tree.key_count, key)?;
+        tree.key_count += 1;
+
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                entry_acc.key,
+                rent.minimum_balance(SparseEntry::LEN),
+                SparseEntry::LEN as u64,
+                program_id,
+            ),
+            &[payer.clone(), entry_acc.clone(), sys.clone()],
+            &[&[SPARSE_ENTRY_SEED, &key, &[entry_key.1]]],
+        )?;
+        EMPTY_HASH
+    } else {
+        let entry = load_sparse_entry(entry_acc)?;
+        hash_sparse_leaf(&key, &entry.value)
+    };
+
+    tree.root = update_root(
+        &tree.root,
+        &key,
+        &proof,
+        old_leaf,
+        hash_sparse_leaf(&key, &value),
+    )?;
+
+    SparseEntry { key, value }.serialize(&mut *entry_acc.try_borrow_mut_data()?)?;
+    tree.serialize(&mut *tree_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+pub fn delete_sparse_value(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    key: SparseKey,
+    slot: u64,
+    proof: SparseProof,
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+
+    let authority = next_account_info(accounts_iterator)?;
+    let tree_acc = next_account_info(accounts_iterator)?;
+    let entry_acc = next_account_info(accounts_iterator)?;
+    let slot_acc = next_account_info(accounts_iterator)?;
+    let last_slot_acc = next_account_info(accounts_iterator)?;
+    let receiver = next_account_info(accounts_iterator)?;
+
+    let mut tree = load_sparse_tree(tree_acc, authority, program_id)?;
+    if *entry_acc.key != find_sparse_entry_pda(&key, program_id).0 || entry_acc.data_is_empty() {
+        return Err(MtreeError::InvalidSparseEntryAccount.into());
+    }
+
+    let entry = load_sparse_entry(entry_acc)?;
+    tree.root = update_root(
+        &tree.root,
+        &key,
+        &proof,
+        hash_sparse_leaf(&key, &entry.value),
+        EMPTY_HASH,
+    )?;
+
+    if load_sparse_slot(slot_acc, slot, program_id)?.key != key {
+        return Err(MtreeError::InvalidSparseSlotAccount.into());
+    }
+    let last_slot = tree.key_count - 1;
+    if slot != last_slot {
+        let last = load_sparse_slot(last_slot_acc, last_slot, program_id)?;
+        last.serialize(&mut *slot_acc.try_borrow_mut_data()?)?;
+    } else if last_slot_acc.key != slot_acc.key {
+        return Err(MtreeError::InvalidSparseSlotAccount.into());
+    }
+    tree.key_count = last_slot;
+    tree.serialize(&mut *tree_acc.try_borrow_mut_data()?)?;
+
+    close_sparse_account(last_slot_acc, receiver)?;
+    close_sparse_account(entry_acc, receiver)
+}
+
+/// Creates the slot account of index `slot` listing `key`.
+fn create_sparse_slot<'a>(
+    slot_acc: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    sys: &AccountInfo<'a>,
+    program_id: &Pubkey,
+    rent: &Rent,
+    slot: u64,
+    key: SparseKey,
+) -> ProgramResult {
+    let slot_key = find_sparse_slot_pda(slot, program_id);
+    if *slot_acc.key != slot_key.0 {
+        return Err(MtreeError::InvalidSparseSlotAccount.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            slot_acc.key,
+            rent.minimum_balance(SparseSlot::LEN),
+            SparseSlot::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), slot_acc.clone(), sys.clone()],
+        &[&[SPARSE_SLOT_SEED, &slot.to_be_bytes(), &[slot_key.1]]],
+    )?;
+    SparseSlot { key }.serialize(&mut *slot_acc.try_borrow_mut_data()?)?;
+    Ok(())
+}
+
+fn close_sparse_account<'a>(acc: &AccountInfo<'a>, receiver: &AccountInfo<'a>) -> ProgramResult {
+    let lamports = acc.lamports();
+    **acc.try_borrow_mut_lamports()? -= lamports;
+    **receiver.try_borrow_mut_lamports()? += lamports;
+
+    acc.realloc(0, false)?;
+    acc.assign(&system_program::ID);
+    Ok(())
+}
+
+/// Checks `proof` of `old_leaf` against `root` and returns the root with
+/// `new_leaf` in its place.
+fn update_root(
+    root: &Hash,
+    key: &SparseKey,
+    proof: &SparseProof,
+    old_leaf: Hash,
+    new_leaf: Hash,
+) -> Result<Hash, MtreeError> {
+    if proof.root(key, old_leaf) != Some(*root) {
+        return Err(MtreeError::InvalidSparseProof);
+    }
+    proof
+        .root(key, new_leaf)
+        .ok_or(MtreeError::InvalidSparseProof)
+}
+
+fn load_sparse_tree(
+    tree_acc: &AccountInfo,
+    authority: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<SparseTree, ProgramError> {
+    assert_signer("authority", authority)?;
+
+    if *tree_acc.key != find_sparse_tree_pda(program_id).0 {
+        return Err(MtreeError::InvalidInfoAccount.into());
+    }
+
+    if tree_acc.data_is_empty() {
+        return Err(MtreeError::UninitializedTree.into());
+    }
+
+    let data = tree_acc.try_borrow_data()?;
+    let tree =
+        SparseTree::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)?;
+    if tree.authority != *authority.key {
+        return Err(MtreeError::InvalidTreeAuthority.into());
+    }
+    Ok(tree)
+}
+
+fn load_sparse_slot(
+    slot_acc: &AccountInfo,
+    slot: u64,
+    program_id: &Pubkey,
+) -> Result<SparseSlot, ProgramError> {
+    if *slot_acc.key != find_sparse_slot_pda(slot, program_id).0 || slot_acc.data_is_empty() {
+        return Err(MtreeError::InvalidSparseSlotAccount.into());
+    }
+
+    let data = slot_acc.try_borrow_data()?;
+    SparseSlot::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+fn load_sparse_entry(entry_acc: &AccountInfo) -> Result<SparseEntry, ProgramError> {
+    let data = entry_acc.try_borrow_data()?;
+    SparseEntry::try_from_slice(data.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
@@ -0,0 +1,298 @@
+//! Portable receipts of inserted leaves, checked offline with the `mtree`
+//! verifier. String and JSON encodings need the `serde` feature.
+
+use crate::info::find_info_pda;
+use crate::mtree::{proof::MerkleProof, Hash};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+use std::io;
+
+/// Version written by `ProofReceipt::new`. Receipts of other versions are
+/// rejected when decoded.
+pub const RECEIPT_VERSION: u8 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiptError {
+    #[error("Unsupported receipt version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Invalid receipt: {0}")]
+    Borsh(#[from] io::Error),
+
+    #[cfg(feature = "serde")]
+    #[error("Invalid receipt JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[cfg(feature = "serde")]
+    #[error("Invalid base58 receipt: {0}")]
+    Base58(#[from] bs58::decode::Error),
+
+    #[cfg(feature = "serde")]
+    #[error("Invalid base64 receipt: {0}")]
+    Base64(#[from] base64::DecodeError),
+}
+
+/// Proof that a leaf is in the tree with `root_hash`, along with where the
+/// leaf comes from.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ProofReceipt {
+    pub version: u8,
+    #[cfg_attr(feature = "serde", serde(with = "serde_helpers::pubkey"))]
+    pub program_id: Pubkey,
+    /// Info account of the tree.
+    #[cfg_attr(feature = "serde", serde(with = "serde_helpers::pubkey"))]
+    pub tree: Pubkey,
+    pub leaf_index: u64,
+    #[cfg_attr(feature = "serde", serde(with = "serde_helpers::hash"))]
+    pub leaf_hash: Hash,
+    /// Position bits of the leaf, as in `MerkleProof`.
+    pub position: u64,
+    #[cfg_attr(feature = "serde", serde(with = "serde_helpers::hashes"))]
+    pub siblings: Vec<Hash>,
+    #[cfg_attr(feature = "serde", serde(with = "serde_helpers::hash"))]
+    pub root_hash: Hash,
+    /// Signature of the transaction that inserted the leaf.
+    #[cfg_attr(feature = "serde", serde(with = "serde_helpers::signature"))]
+    pub signature: [u8; 64],
+    /// Slot of the transaction that inserted the leaf.
+    pub slot: u64,
+}
+
+impl ProofReceipt {
+    pub fn new(
+        program_id: Pubkey,
+        leaf_index: u64,
+        leaf_hash: Hash,
+        proof: MerkleProof,
+        root_hash: Hash,
+        signature: [u8; 64],
+        slot: u64,
+    ) -> Self {
+        Self {
+            version: RECEIPT_VERSION,
+            program_id,
+            tree: find_info_pda(&program_id).0,
+            leaf_index,
+            leaf_hash,
+            position: proof.position,
+            siblings: proof.siblings,
+            root_hash,
+            signature,
+            slot,
+        }
+    }
+
+    pub fn proof(&self) -> MerkleProof {
+        MerkleProof {
+            position: self.position,
+            siblings: self.siblings.clone(),
+        }
+    }
+
+    /// Checks that the leaf is in the tree with `root_hash` and that `tree`
+    /// is the tree of `program_id`. Whether `root_hash` is a root the tree
+    /// actually had is up to the verifier.
+    pub fn verify(&self) -> bool {
+        self.tree == find_info_pda(&self.program_id).0
+            && self.proof().verify(&self.root_hash, self.leaf_hash)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, io::Error> {
+        self.try_to_vec()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ReceiptError> {
+        Self::try_from_slice(data)?.check_version()
+    }
+
+    fn check_version(self) -> Result<Self, ReceiptError> {
+        if self.version != RECEIPT_VERSION {
+            return Err(ReceiptError::UnsupportedVersion(self.version));
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ProofReceipt {
+    pub fn to_json(&self) -> Result<String, ReceiptError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ReceiptError> {
+        serde_json::from_str::<Self>(json)?.check_version()
+    }
+
+    /// Encodes the Borsh bytes of the receipt to base58.
+    pub fn to_base58(&self) -> Result<String, ReceiptError> {
+        Ok(bs58::encode(self.to_bytes()?).into_string())
+    }
+
+    pub fn from_base58(data: &str) -> Result<Self, ReceiptError> {
+        Self::from_bytes(&bs58::decode(data).into_vec()?)
+    }
+
+    /// Encodes the Borsh bytes of the receipt to base64.
+    pub fn to_base64(&self) -> Result<String, ReceiptError> {
+        use base64::Engine as _;
+        Ok(base64::engine::general_purpose::STANDARD.encode(self.to_bytes()?))
+    }
+
+    pub fn from_base64(data: &str) -> Result<Self, ReceiptError> {
+        use base64::Engine as _;
+        Self::from_bytes(&base64::engine::general_purpose::STANDARD.decode(data)?)
+    }
+}
+
+/// JSON representations: base58 for keys and signatures, like the Solana
+/// tools, and hex for hashes, like the events.
+#[cfg(feature = "serde")]
+mod serde_helpers {
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    fn decode_hex<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[u8; N], D::Error> {
+        let data = String::deserialize(deserializer)?;
+        let bytes = hex::decode(data).map_err(D::Error::custom)?;
+        bytes
+            .try_into()
+            .map_err(|_| D::Error::custom("invalid hash length"))
+    }
+
+    pub mod pubkey {
+        use super::*;
+        use solana_program::pubkey::Pubkey;
+        use std::str::FromStr as _;
+
+        pub fn serialize<S: Serializer>(key: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&key.to_string())
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+            let data = String::deserialize(deserializer)?;
+            Pubkey::from_str(&data).map_err(D::Error::custom)
+        }
+    }
+
+    pub mod signature {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            signature: &[u8; 64],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&bs58::encode(signature).into_string())
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<[u8; 64], D::Error> {
+            let data = String::deserialize(deserializer)?;
+            let bytes = bs58::decode(data).into_vec().map_err(D::Error::custom)?;
+            bytes
+                .try_into()
+                .map_err(|_| D::Error::custom("invalid signature length"))
+        }
+    }
+
+    pub mod hash {
+        use super::*;
+        use crate::mtree::Hash;
+
+        pub fn serialize<S: Serializer>(hash: &Hash, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&hex::encode(hash))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Hash, D::Error> {
+            decode_hex(deserializer)
+        }
+    }
+
+    pub mod hashes {
+        use super::*;
+        use crate::mtree::Hash;
+        use serde::Serialize as _;
+
+        pub fn serialize<S: Serializer>(hashes: &[Hash], serializer: S) -> Result<S::Ok, S::Error> {
+            hashes
+                .iter()
+                .map(hex::encode)
+                .collect::<Vec<_>>()
+                .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<Hash>, D::Error> {
+            Vec::<String>::deserialize(deserializer)?
+                .into_iter()
+                .map(|data| {
+                    let bytes = hex::decode(data).map_err(D::Error::custom)?;
+                    bytes
+                        .try_into()
+                        .map_err(|_| D::Error::custom("invalid hash length"))
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtree::{hash_leaf, sub_tree::SubTree};
+
+    fn receipt() -> ProofReceipt {
+        let mut tree = SubTree::new();
+        for i in 0..3u8 {
+            tree.insert_leaf(hash_leaf([i]));
+        }
+        let proof = MerkleProof::from_sub_trees(&[0], &[tree.clone()], 2).unwrap();
+        ProofReceipt::new(
+            crate::ID,
+            2,
+            hash_leaf([2]),
+            proof,
+            tree.root_hash(),
+            [7; 64],
+            100,
+        )
+    }
+
+    #[test]
+    fn test_receipt_bytes() {
+        let receipt = receipt();
+        assert!(receipt.verify());
+
+        let bytes = receipt.to_bytes().unwrap();
+        assert_eq!(ProofReceipt::from_bytes(&bytes).unwrap(), receipt);
+
+        let mut bytes = bytes;
+        bytes[0] = RECEIPT_VERSION + 1;
+        assert!(matches!(
+            ProofReceipt::from_bytes(&bytes),
+            Err(ReceiptError::UnsupportedVersion(_))
+        ));
+
+        let mut forged = receipt;
+        forged.leaf_hash = hash_leaf([3]);
+        assert!(!forged.verify());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_receipt_strings() {
+        let receipt = receipt();
+
+        let json = receipt.to_json().unwrap();
+        assert!(json.contains(&hex::encode(receipt.root_hash)));
+        assert_eq!(ProofReceipt::from_json(&json).unwrap(), receipt);
+
+        let base58 = receipt.to_base58().unwrap();
+        assert_eq!(ProofReceipt::from_base58(&base58).unwrap(), receipt);
+        let base64 = receipt.to_base64().unwrap();
+        assert_eq!(ProofReceipt::from_base64(&base64).unwrap(), receipt);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::mtree::Hash;
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::mem::size_of;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::{get_return_data, set_return_data};
+use solana_program::pubkey::Pubkey;
+use std::io;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct InsertLeafReturn {
+    pub leaf_index: u64,
+    pub leaf_hash: Hash,
+    pub root_hash: Hash,
+}
+
+impl InsertLeafReturn {
+    pub const LEN: usize = size_of::<u64>() + size_of::<Hash>() + size_of::<Hash>();
+
+    pub fn set(&self) -> ProgramResult {
+        set_return_data(&self.try_to_vec()?);
+        Ok(())
+    }
+
+    /// Decodes the return data of `InsertLeaf`. The runtime strips trailing
+    /// zero bytes from return data, so they are restored before decoding.
+    pub fn from_return_data(data: &[u8]) -> Result<Self, io::Error> {
+        let mut data = data.to_vec();
+        if data.len() < Self::LEN {
+            data.resize(Self::LEN, 0);
+        }
+        Self::try_from_slice(&data)
+    }
+
+    /// Reads the result of an `InsertLeaf` invoked through CPI. Returns `None`
+    /// if the last return data was not set by the mtree program.
+    pub fn from_cpi(program_id: &Pubkey) -> Option<Self> {
+        let (returned_by, data) = get_return_data()?;
+        if returned_by != *program_id {
+            return None;
+        }
+        Self::from_return_data(&data).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borsh_insert_leaf_return_size() {
+        let size = InsertLeafReturn::default().try_to_vec().unwrap().len();
+        assert_eq!(size, InsertLeafReturn::LEN);
+    }
+
+    #[test]
+    fn test_from_return_data_restores_trailing_zeros() {
+        let result = InsertLeafReturn {
+            leaf_index: 3,
+            leaf_hash: [1; 32],
+            root_hash: [0; 32],
+        };
+        let data = result.try_to_vec().unwrap();
+        let trimmed = &data[..InsertLeafReturn::LEN - 32];
+        assert_eq!(InsertLeafReturn::from_return_data(trimmed).unwrap(), result);
+    }
+}
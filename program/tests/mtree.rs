@@ -1,15 +1,47 @@
-use borsh::BorshDeserialize as _;
+use borsh::{BorshDeserialize as _, BorshSerialize as _};
 use solana_program_mtree::{
-    info::{find_info_pda, find_sub_tree_pda, MTreeInfo},
-    instruction::encode::make_insert_leaf_instruction,
+    cpi::{self, InsertLeafAccounts},
+    info::{
+        find_buffer_pda, find_indexed_leaf_pda, find_indexed_tree_pda, find_info_pda,
+        find_leaf_marker_pda, find_mmr_page_pda, find_program_data_pda, find_signed_leaf_pda,
+        find_sparse_entry_pda, find_sparse_slot_pda, find_sparse_tree_pda, find_sub_tree_pda,
+        signed_leaf_message, signed_leaf_message_hash, LeafMarker, LeafMode, LeafPolicy,
+        LegacyMTreeInfo, MTreeInfo, SignedLeafMarker, TreeConfig, INFO_VERSION,
+    },
+    instruction::encode::{
+        add_leaf_marker_account, add_next_sub_tree_accounts, decode_insert_leaf_return,
+        insert_leaf_account_metas, make_append_mmr_leaf_instruction, make_close_buffer_instruction,
+        make_delete_sparse_value_instruction, make_init_buffer_instruction,
+        make_init_indexed_tree_instruction, make_init_sparse_tree_instruction,
+        make_init_tree_instruction, make_insert_authored_leaf_instruction,
+        make_insert_from_buffer_instruction, make_insert_indexed_instruction,
+        make_insert_leaf_instruction, make_insert_signed_leaf_instructions,
+        make_migrate_info_instruction, make_set_sparse_value_instruction,
+        make_verify_leaves_instruction, make_write_buffer_instruction,
+    },
     mtree::{
-        hash_leaf,
+        hash_authored_leaf, hash_leaf,
+        indexed::{IndexedLeaf, IndexedTree, IndexedTreeLeaves},
+        local::LocalTree,
+        mmr::{MmrLeaves, MMR_PAGE_LEAVES, MMR_PAGE_SIZE},
+        path::get_path_to_root,
+        proof::{verify_authored_inclusion, verify_inclusion, MerkleMultiProof, MerkleProof},
+        sparse::{SparseEntry, SparseKey, SparseSlot, SparseTree, SparseTreeEntries},
         sub_tree::{SubTree, SUB_TREE_LEAFS},
         SubTreeId,
     },
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        bpf_loader_upgradeable,
+        entrypoint::ProgramResult,
+        instruction::{AccountMeta, Instruction},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        rent::Rent,
+    },
 };
-use solana_program_test::{BanksClientError, ProgramTest, ProgramTestContext};
-use solana_sdk::{signer::Signer, transaction::Transaction};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{account::Account, signature::Keypair, signer::Signer, transaction::Transaction};
 
 #[tokio::test]
 pub async fn test_init() {
@@ -32,12 +64,127 @@ pub async fn test_init() {
     assert!(info.is_some());
     let info = info.unwrap();
     assert_eq!(info.tree_id, 0);
+    assert_eq!(info.leaf_count, 1);
 
     let mut tree = SubTree::new();
     tree.insert_leaf(hash_leaf(test_data));
     assert_eq!(tree.root_hash(), info.root_hash);
 }
 
+#[tokio::test]
+pub async fn test_migrate_info() {
+    let mut program_test = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None);
+
+    // A legacy tree whose next leaf goes to sub tree 1, holding the leaf
+    // moved down from the root and two inserted leaves.
+    let mut sub_tree = SubTree::new();
+    for i in 0..3u8 {
+        sub_tree.insert_leaf(hash_leaf([i]));
+    }
+    let legacy = LegacyMTreeInfo {
+        tree_id: 1,
+        root_hash: [7; 32],
+    };
+    let rent = Rent::default();
+    for (key, data) in [
+        (
+            find_info_pda(&solana_program_mtree::ID).0,
+            legacy.try_to_vec().unwrap(),
+        ),
+        (
+            find_sub_tree_pda(1, &solana_program_mtree::ID).0,
+            sub_tree.try_to_vec().unwrap(),
+        ),
+    ] {
+        program_test.add_account(
+            key,
+            Account {
+                lamports: rent.minimum_balance(data.len()),
+                data,
+                owner: solana_program_mtree::ID,
+                ..Default::default()
+            },
+        );
+    }
+    let mut context = program_test.start_with_context().await;
+
+    assert!(insert_leaf(&mut context, vec![1], 1).await.is_err());
+
+    let migrate_instruction = make_migrate_info_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        legacy.tree_id,
+    )
+    .unwrap();
+    process_instructions(&mut context, &[migrate_instruction.clone()], &[])
+        .await
+        .unwrap();
+
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.tree_id, 1);
+    assert_eq!(info.root_hash, [7; 32]);
+    assert_eq!(info.version, INFO_VERSION);
+    assert_eq!(info.leaf_count, 9);
+    assert_eq!(info.config, TreeConfig::default());
+
+    let info_acc = context
+        .banks_client
+        .get_account(find_info_pda(&solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info_acc.data.len(), MTreeInfo::LEN);
+    assert!(info_acc.lamports >= rent.minimum_balance(MTreeInfo::LEN));
+
+    context.get_new_latest_blockhash().await.unwrap();
+    assert!(
+        process_instructions(&mut context, &[migrate_instruction], &[])
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+pub async fn test_insert_leaf_return_data() {
+    let context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let mut expected_tree = SubTree::new();
+    for i in 0..3 {
+        let test_data = format!("test_data_{}", i).as_bytes().to_vec();
+        let insert_instruction = make_insert_leaf_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            context.payer.pubkey(),
+            test_data.clone(),
+            0,
+        )
+        .unwrap();
+        let tx = Transaction::new_signed_with_payer(
+            &[insert_instruction],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        );
+        let result = context
+            .banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .unwrap();
+        result.result.unwrap();
+
+        let return_data = result.metadata.unwrap().return_data.unwrap();
+        assert_eq!(return_data.program_id, solana_program_mtree::ID);
+        let insert_result = decode_insert_leaf_return(&return_data.data).unwrap();
+
+        expected_tree.insert_leaf(hash_leaf(test_data.clone()));
+        assert_eq!(insert_result.leaf_index, i);
+        assert_eq!(insert_result.leaf_hash, hash_leaf(test_data));
+        assert_eq!(insert_result.root_hash, expected_tree.root_hash());
+    }
+}
+
 #[tokio::test]
 pub async fn test_full_root_sub_tree() {
     let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
@@ -86,12 +233,66 @@ pub async fn insert_to_wrong_sub_tree() {
         .start_with_context()
         .await;
 
+    for i in 0..SUB_TREE_LEAFS - 1 {
+        let test_data = format!("test_data_{}", i).as_bytes().to_vec();
+        insert_leaf(&mut context, test_data, 0).await.unwrap();
+    }
+
+    // The path of sub tree 2 misses sub tree 1, the current one.
     let test_data = "test_data".as_bytes().to_vec();
-    assert!(insert_leaf(&mut context, test_data.clone(), 1)
+    assert!(insert_leaf(&mut context, test_data.clone(), 2)
         .await
         .is_err());
 }
 
+#[tokio::test]
+pub async fn test_insert_with_next_path() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let mut expected_tree = SubTree::new();
+    for i in 0..SUB_TREE_LEAFS - 2 {
+        let test_data = format!("test_data_{}", i).as_bytes().to_vec();
+        insert_leaf(&mut context, test_data.clone(), 0)
+            .await
+            .unwrap();
+        expected_tree.insert_leaf(hash_leaf(test_data));
+    }
+
+    // Both inserts are built for sub tree 0, the second one lands in sub
+    // tree 1 once the first one fills the root sub tree.
+    for data in ["last_root_leaf", "first_child_leaf"] {
+        let mut insert_instruction = make_insert_leaf_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            context.payer.pubkey(),
+            data.as_bytes().to_vec(),
+            0,
+        )
+        .unwrap();
+        add_next_sub_tree_accounts(&mut insert_instruction, 0);
+        let tx = Transaction::new_signed_with_payer(
+            &[insert_instruction],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        );
+        context.banks_client.process_transaction(tx).await.unwrap();
+    }
+    expected_tree.insert_leaf(hash_leaf("last_root_leaf"));
+
+    let mut child_tree = SubTree::new();
+    child_tree.insert_leaf(expected_tree.get_leaf(0).unwrap());
+    child_tree.insert_leaf(hash_leaf("first_child_leaf"));
+    expected_tree.update_leaf(SUB_TREE_LEAFS - 1, child_tree.root_hash());
+
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.tree_id, 1);
+    assert_eq!(info.leaf_count, SUB_TREE_LEAFS as u64);
+    assert_eq!(info.root_hash, expected_tree.root_hash());
+}
+
 #[tokio::test]
 pub async fn test_full_tree() {
     let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
@@ -120,10 +321,1157 @@ pub async fn test_full_tree() {
             .unwrap();
         sub_tree.insert_leaf(hash_leaf(test_data));
     }
-    root_sub_tree.insert_leaf(sub_tree.root_hash());
+    root_sub_tree.update_leaf(SUB_TREE_LEAFS - 1, sub_tree.root_hash());
     let info = get_info(&mut context).await.unwrap();
 
     assert_eq!(info.root_hash, root_sub_tree.root_hash());
+    assert_eq!(info.leaf_count, 2 * SUB_TREE_LEAFS as u64 - 3);
+
+    let (id, position) = info.leaf_position(0).unwrap();
+    assert_eq!((id, position), (1, 0));
+    let relocated = get_sub_tree(&mut context, id).await.unwrap();
+    assert_eq!(relocated.get_leaf(position), Some(moved_to_leaf));
+}
+
+#[tokio::test]
+pub async fn test_local_tree() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let mut local_tree = LocalTree::new();
+    for i in 0..10 * SUB_TREE_LEAFS {
+        let test_data = format!("test_data_{}", i).as_bytes().to_vec();
+        insert_leaf(&mut context, test_data.clone(), local_tree.tree_id())
+            .await
+            .unwrap();
+        local_tree.insert_leaf(hash_leaf(test_data));
+    }
+
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.tree_id, local_tree.tree_id());
+    assert_eq!(info.leaf_count, local_tree.leaf_count());
+    assert_eq!(info.root_hash, local_tree.root_hash());
+    for (id, sub_tree) in local_tree.sub_trees().iter().enumerate() {
+        let on_chain = get_sub_tree(&mut context, id as SubTreeId).await.unwrap();
+        assert_eq!(on_chain.root_hash(), sub_tree.root_hash());
+    }
+}
+
+#[tokio::test]
+pub async fn test_insert_authored_leaf_paid_by_relayer() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let author = Keypair::new();
+    let test_data = "test_data".as_bytes().to_vec();
+    let insert_instruction = make_insert_authored_leaf_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        author.pubkey(),
+        test_data.clone(),
+        0,
+    )
+    .unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[insert_instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &author],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let mut tree = SubTree::new();
+    tree.insert_leaf(hash_authored_leaf(&author.pubkey(), &test_data));
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.root_hash, tree.root_hash());
+}
+
+#[tokio::test]
+pub async fn test_author_bound_tree() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    let config = TreeConfig {
+        leaf_mode: LeafMode::AuthorBound,
+        ..Default::default()
+    };
+    init_tree(&mut context, &upgrade_authority, config.clone())
+        .await
+        .unwrap();
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.config, config);
+    assert_eq!(info.leaf_count, 0);
+
+    let test_data = "test_data".as_bytes().to_vec();
+    insert_leaf(&mut context, test_data.clone(), 0)
+        .await
+        .unwrap();
+
+    let author = context.payer.pubkey();
+    let info = get_info(&mut context).await.unwrap();
+    let (id, position) = info.leaf_position(0).unwrap();
+    let sub_tree = get_sub_tree(&mut context, id).await.unwrap();
+    let proof = MerkleProof::from_sub_trees(&get_path_to_root(id), &[sub_tree], position).unwrap();
+    assert!(verify_authored_inclusion(
+        &info.root_hash,
+        0,
+        info.leaf_count,
+        &author,
+        &test_data,
+        &proof
+    ));
+    assert!(!verify_inclusion(
+        &info.root_hash,
+        0,
+        info.leaf_count,
+        &test_data,
+        &proof
+    ));
+
+    assert!(
+        init_tree(&mut context, &upgrade_authority, TreeConfig::default())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+pub async fn test_leaf_policy() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    let conflicting = TreeConfig {
+        leaf_policy: LeafPolicy {
+            min_len: 8,
+            max_len: 4,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(init_tree(&mut context, &upgrade_authority, conflicting)
+        .await
+        .is_err());
+
+    let config = TreeConfig {
+        leaf_policy: LeafPolicy {
+            reject_empty: true,
+            fixed_len: 4,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    init_tree(&mut context, &upgrade_authority, config)
+        .await
+        .unwrap();
+
+    assert!(insert_leaf(&mut context, vec![], 0).await.is_err());
+    assert!(insert_leaf(&mut context, vec![1, 2, 3], 0).await.is_err());
+    insert_leaf(&mut context, vec![1, 2, 3, 4], 0)
+        .await
+        .unwrap();
+
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.leaf_count, 1);
+}
+
+#[tokio::test]
+pub async fn test_init_tree_requires_upgrade_authority() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    let forger = Keypair::new();
+    assert!(init_tree(&mut context, &forger, TreeConfig::default())
+        .await
+        .is_err());
+    assert!(get_info(&mut context).await.is_none());
+
+    init_tree(&mut context, &upgrade_authority, TreeConfig::default())
+        .await
+        .unwrap();
+    assert!(get_info(&mut context).await.is_some());
+}
+
+#[tokio::test]
+pub async fn test_dedup_tree() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    let config = TreeConfig {
+        dedup: true,
+        ..Default::default()
+    };
+    init_tree(&mut context, &upgrade_authority, config)
+        .await
+        .unwrap();
+
+    let test_data = "document".as_bytes().to_vec();
+    let leaf_hash = hash_leaf(&test_data);
+    let make_insert = |context: &ProgramTestContext, data: Vec<u8>| {
+        let mut instruction = make_insert_leaf_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            context.payer.pubkey(),
+            data,
+            0,
+        )
+        .unwrap();
+        add_leaf_marker_account(&mut instruction, &leaf_hash);
+        instruction
+    };
+
+    let insert_instruction = make_insert(&context, test_data.clone());
+    process_instructions(&mut context, &[insert_instruction], &[])
+        .await
+        .unwrap();
+
+    let marker_acc = context
+        .banks_client
+        .get_account(find_leaf_marker_pda(&leaf_hash, &solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    let marker = LeafMarker::try_from_slice(&marker_acc.data).unwrap();
+    assert_eq!(marker.leaf_index, 0);
+
+    // A new blockhash, so the retry is not rejected as an already processed
+    // transaction.
+    context.get_new_latest_blockhash().await.unwrap();
+    let insert_instruction = make_insert(&context, test_data);
+    assert!(
+        process_instructions(&mut context, &[insert_instruction], &[])
+            .await
+            .is_err()
+    );
+
+    // The marker must belong to the hash of the inserted leaf.
+    let insert_instruction = make_insert(&context, "other document".as_bytes().to_vec());
+    assert!(
+        process_instructions(&mut context, &[insert_instruction], &[])
+            .await
+            .is_err()
+    );
+
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.leaf_count, 1);
+}
+
+#[tokio::test]
+pub async fn test_insert_signed_leaf() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    let oracle = Keypair::new();
+    let config = TreeConfig {
+        leaf_signer: oracle.pubkey(),
+        ..Default::default()
+    };
+    init_tree(&mut context, &upgrade_authority, config)
+        .await
+        .unwrap();
+
+    let info_pda = find_info_pda(&solana_program_mtree::ID).0;
+    let test_data = "signed_record".as_bytes().to_vec();
+    let message = signed_leaf_message(&info_pda, 7, &test_data);
+    let signature = oracle.sign_message(&message);
+    insert_signed_leaf(
+        &mut context,
+        oracle.pubkey(),
+        signature.into(),
+        7,
+        test_data.clone(),
+    )
+    .await
+    .unwrap();
+
+    let mut tree = SubTree::new();
+    tree.insert_leaf(hash_leaf(&test_data));
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.root_hash, tree.root_hash());
+
+    let marker_acc = context
+        .banks_client
+        .get_account(
+            find_signed_leaf_pda(
+                &signed_leaf_message_hash(&message),
+                &solana_program_mtree::ID,
+            )
+            .0,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    SignedLeafMarker::try_from_slice(&marker_acc.data).unwrap();
+
+    // The message has its replay marker, the same signature cannot be
+    // replayed, nor reused with another nonce.
+    context.get_new_latest_blockhash().await.unwrap();
+    for nonce in [7, 8] {
+        assert!(insert_signed_leaf(
+            &mut context,
+            oracle.pubkey(),
+            signature.into(),
+            nonce,
+            test_data.clone(),
+        )
+        .await
+        .is_err());
+    }
+
+    // Approvals do not depend on each other, a lower nonce signed later is
+    // accepted, here approving the same leaf a second time.
+    let signature = oracle.sign_message(&signed_leaf_message(&info_pda, 3, &test_data));
+    insert_signed_leaf(
+        &mut context,
+        oracle.pubkey(),
+        signature.into(),
+        3,
+        test_data.clone(),
+    )
+    .await
+    .unwrap();
+    tree.insert_leaf(hash_leaf(&test_data));
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.root_hash, tree.root_hash());
+
+    // A signature of the bare leaf data is rejected.
+    let signature = oracle.sign_message(&test_data);
+    assert!(insert_signed_leaf(
+        &mut context,
+        oracle.pubkey(),
+        signature.into(),
+        1,
+        test_data.clone(),
+    )
+    .await
+    .is_err());
+
+    let forged_data = "forged_record".as_bytes().to_vec();
+    let forger = Keypair::new();
+    let signature = forger.sign_message(&signed_leaf_message(&info_pda, 1, &forged_data));
+    assert!(insert_signed_leaf(
+        &mut context,
+        forger.pubkey(),
+        signature.into(),
+        1,
+        forged_data
+    )
+    .await
+    .is_err());
+
+    let unsigned_data = "unsigned_record".as_bytes().to_vec();
+    let mut instructions = make_insert_signed_leaf_instructions(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        oracle.pubkey(),
+        &[0; 64],
+        1,
+        unsigned_data,
+        0,
+    )
+    .unwrap();
+    instructions.remove(0);
+    let tx = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+}
+
+#[tokio::test]
+pub async fn test_insert_from_buffer() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let test_data = (0..20_000u32).map(|i| i as u8).collect::<Vec<_>>();
+    let nonce = 7;
+    let init_instruction = make_init_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        nonce,
+        test_data.len() as u32,
+        false,
+    )
+    .unwrap();
+    process_instructions(&mut context, &[init_instruction], &[])
+        .await
+        .unwrap();
+
+    let incomplete = make_insert_from_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        nonce,
+        0,
+    )
+    .unwrap();
+    assert!(process_instructions(&mut context, &[incomplete], &[])
+        .await
+        .is_err());
+
+    for (i, chunk) in test_data.chunks(900).enumerate() {
+        let write_instruction = make_write_buffer_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            nonce,
+            (i * 900) as u32,
+            chunk.to_vec(),
+        )
+        .unwrap();
+        process_instructions(&mut context, &[write_instruction], &[])
+            .await
+            .unwrap();
+    }
+
+    // Written chunks are already hashed and cannot be rewritten.
+    let rewrite = make_write_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        nonce,
+        0,
+        vec![0; 900],
+    )
+    .unwrap();
+    assert!(process_instructions(&mut context, &[rewrite], &[])
+        .await
+        .is_err());
+
+    let out_of_bounds = make_write_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        nonce,
+        test_data.len() as u32,
+        vec![0; 2],
+    )
+    .unwrap();
+    assert!(process_instructions(&mut context, &[out_of_bounds], &[])
+        .await
+        .is_err());
+
+    let insert_instruction = make_insert_from_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        nonce,
+        0,
+    )
+    .unwrap();
+    process_instructions(&mut context, &[insert_instruction], &[])
+        .await
+        .unwrap();
+
+    let mut tree = SubTree::new();
+    tree.insert_leaf(hash_leaf(&test_data));
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.leaf_count, 1);
+    assert_eq!(info.root_hash, tree.root_hash());
+
+    let buffer = find_buffer_pda(&context.payer.pubkey(), nonce, &solana_program_mtree::ID).0;
+    let buffer_account = context.banks_client.get_account(buffer).await.unwrap();
+    assert!(buffer_account.is_none());
+}
+
+#[tokio::test]
+pub async fn test_close_buffer() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let nonce = 1;
+    let init_instruction = make_init_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        nonce,
+        2_000,
+        false,
+    )
+    .unwrap();
+    let write_instruction = make_write_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        nonce,
+        0,
+        vec![1; 900],
+    )
+    .unwrap();
+    process_instructions(&mut context, &[init_instruction, write_instruction], &[])
+        .await
+        .unwrap();
+
+    let buffer = find_buffer_pda(&context.payer.pubkey(), nonce, &solana_program_mtree::ID).0;
+    let buffer_lamports = context.banks_client.get_balance(buffer).await.unwrap();
+    let receiver = Pubkey::new_unique();
+
+    // Only the authority closes its buffer.
+    let intruder = Keypair::new();
+    let mut stolen_close = make_close_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        nonce,
+        intruder.pubkey(),
+    )
+    .unwrap();
+    stolen_close.accounts[0] = AccountMeta::new_readonly(intruder.pubkey(), true);
+    assert!(
+        process_instructions(&mut context, &[stolen_close], &[&intruder])
+            .await
+            .is_err()
+    );
+
+    let close_instruction = make_close_buffer_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        nonce,
+        receiver,
+    )
+    .unwrap();
+    process_instructions(&mut context, &[close_instruction], &[])
+        .await
+        .unwrap();
+
+    let buffer_account = context.banks_client.get_account(buffer).await.unwrap();
+    assert!(buffer_account.is_none());
+    let receiver_lamports = context.banks_client.get_balance(receiver).await.unwrap();
+    assert_eq!(receiver_lamports, buffer_lamports);
+}
+
+#[tokio::test]
+pub async fn test_buffer_rejects_program_accounts() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    init_tree(&mut context, &upgrade_authority, TreeConfig::default())
+        .await
+        .unwrap();
+    let init_indexed =
+        make_init_indexed_tree_instruction(solana_program_mtree::ID, context.payer.pubkey())
+            .unwrap();
+    process_instructions(&mut context, &[init_indexed], &[])
+        .await
+        .unwrap();
+
+    let info_pda = find_info_pda(&solana_program_mtree::ID).0;
+    let indexed_leaf_pda = find_indexed_leaf_pda(0, &solana_program_mtree::ID).0;
+    for account in [info_pda, indexed_leaf_pda] {
+        let before = context
+            .banks_client
+            .get_account(account)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut init = make_init_buffer_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            context.payer.pubkey(),
+            0,
+            1,
+            false,
+        )
+        .unwrap();
+        init.accounts[2].pubkey = account;
+        let mut write = make_write_buffer_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            0,
+            0,
+            vec![1],
+        )
+        .unwrap();
+        write.accounts[1].pubkey = account;
+        let mut insert = make_insert_from_buffer_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            context.payer.pubkey(),
+            0,
+            0,
+        )
+        .unwrap();
+        insert.accounts[4].pubkey = account;
+        let mut close = make_close_buffer_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            0,
+            context.payer.pubkey(),
+        )
+        .unwrap();
+        close.accounts[1].pubkey = account;
+
+        for instruction in [init, write, insert, close] {
+            assert!(process_instructions(&mut context, &[instruction], &[])
+                .await
+                .is_err());
+        }
+
+        let after = context
+            .banks_client
+            .get_account(account)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(before, after);
+    }
+}
+
+#[tokio::test]
+pub async fn test_indexed_tree() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let init_instruction =
+        make_init_indexed_tree_instruction(solana_program_mtree::ID, context.payer.pubkey())
+            .unwrap();
+    process_instructions(&mut context, &[init_instruction], &[])
+        .await
+        .unwrap();
+
+    let values = [30u8, 10, 20].map(|byte| {
+        let mut value = [0; 32];
+        value[31] = byte;
+        value
+    });
+    for value in values {
+        let leaves = get_indexed_leaves(&mut context).await;
+        let low_leaf_index = leaves.find_low_leaf(&value).unwrap();
+        let insert_instruction = make_insert_indexed_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            value,
+            low_leaf_index,
+            leaves.get_proof(low_leaf_index).unwrap().siblings,
+            leaves.leaves().len() as u64,
+        )
+        .unwrap();
+        process_instructions(&mut context, &[insert_instruction], &[])
+            .await
+            .unwrap();
+    }
+
+    let leaves = get_indexed_leaves(&mut context).await;
+    assert_eq!(leaves.leaves().len(), 4);
+
+    // A value is only accepted after its low leaf.
+    let insert_instruction = make_insert_indexed_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        values[0],
+        0,
+        leaves.get_proof(0).unwrap().siblings,
+        4,
+    )
+    .unwrap();
+    assert!(
+        process_instructions(&mut context, &[insert_instruction], &[])
+            .await
+            .is_err()
+    );
+
+    let tree_acc = context
+        .banks_client
+        .get_account(find_indexed_tree_pda(&solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    let tree = IndexedTree::try_from_slice(&tree_acc.data).unwrap();
+
+    let proof = leaves.membership_proof(&values[2]).unwrap();
+    assert!(proof.verify_membership(&tree.root, &values[2]));
+
+    let mut absent = values[2];
+    absent[31] += 1;
+    let proof = leaves.non_membership_proof(&absent).unwrap();
+    assert_eq!(proof.leaf.value, values[2]);
+    assert!(proof.verify_non_membership(&tree.root, &absent));
+}
+
+#[tokio::test]
+pub async fn test_sparse_tree() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let authority = Keypair::new();
+    let init_instruction = make_init_sparse_tree_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        authority.pubkey(),
+    )
+    .unwrap();
+    process_instructions(&mut context, &[init_instruction], &[&authority])
+        .await
+        .unwrap();
+
+    let mut entries = Vec::new();
+    for (key, value) in [
+        ([1; 32], [10; 32]),
+        ([2; 32], [20; 32]),
+        ([3; 32], [30; 32]),
+        ([1; 32], [40; 32]),
+    ] {
+        let key_count = get_sparse_tree(&mut context).await.key_count;
+        let proof = SparseTreeEntries::new(entries.clone()).get_proof(&key);
+        let set_instruction = make_set_sparse_value_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            authority.pubkey(),
+            key_count,
+            key,
+            value,
+            proof,
+        )
+        .unwrap();
+        process_instructions(&mut context, &[set_instruction], &[&authority])
+            .await
+            .unwrap();
+
+        entries.retain(|entry: &SparseEntry| entry.key != key);
+        entries.push(SparseEntry { key, value });
+        assert_eq!(
+            get_sparse_tree(&mut context).await.root,
+            SparseTreeEntries::new(entries.clone()).root()
+        );
+    }
+
+    // Each key is listed by a slot account, so entries are found without
+    // scanning the program accounts.
+    assert_eq!(get_sparse_tree(&mut context).await.key_count, 3);
+    assert_eq!(
+        get_sparse_keys(&mut context).await,
+        vec![[1; 32], [2; 32], [3; 32]]
+    );
+
+    let forger = Keypair::new();
+    let set_instruction = make_set_sparse_value_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        forger.pubkey(),
+        3,
+        [4; 32],
+        [50; 32],
+        SparseTreeEntries::new(entries.clone()).get_proof(&[4; 32]),
+    )
+    .unwrap();
+    assert!(
+        process_instructions(&mut context, &[set_instruction], &[&forger])
+            .await
+            .is_err()
+    );
+
+    // Deleting a key moves the key of the last slot into its slot.
+    for (key, slot, remaining_keys) in [
+        ([1; 32], 0, vec![[3; 32], [2; 32]]),
+        ([2; 32], 1, vec![[3; 32]]),
+    ] {
+        let key_count = get_sparse_tree(&mut context).await.key_count;
+        let delete_instruction = make_delete_sparse_value_instruction(
+            solana_program_mtree::ID,
+            authority.pubkey(),
+            context.payer.pubkey(),
+            key_count,
+            key,
+            slot,
+            SparseTreeEntries::new(entries.clone()).get_proof(&key),
+        )
+        .unwrap();
+        process_instructions(&mut context, &[delete_instruction], &[&authority])
+            .await
+            .unwrap();
+
+        let entry_acc = context
+            .banks_client
+            .get_account(find_sparse_entry_pda(&key, &solana_program_mtree::ID).0)
+            .await
+            .unwrap();
+        assert!(entry_acc.is_none());
+        let last_slot_acc = context
+            .banks_client
+            .get_account(find_sparse_slot_pda(key_count - 1, &solana_program_mtree::ID).0)
+            .await
+            .unwrap();
+        assert!(last_slot_acc.is_none());
+
+        entries.retain(|entry| entry.key != key);
+        assert_eq!(get_sparse_keys(&mut context).await, remaining_keys);
+    }
+
+    let tree_acc = context
+        .banks_client
+        .get_account(find_sparse_tree_pda(&solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(tree_acc.data.len(), SparseTree::LEN);
+
+    let remaining = SparseTreeEntries::new(entries);
+    let root = get_sparse_tree(&mut context).await.root;
+    assert_eq!(root, remaining.root());
+    assert!(remaining.get_proof(&[1; 32]).verify_empty(&root, &[1; 32]));
+    assert!(remaining
+        .get_proof(&[3; 32])
+        .verify(&root, &[3; 32], &[30; 32]));
+}
+
+#[tokio::test]
+pub async fn test_sparse_tree_requires_authority_signature() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let mut init_instruction = make_init_sparse_tree_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        Keypair::new().pubkey(),
+    )
+    .unwrap();
+    init_instruction.accounts[1].is_signer = false;
+    assert!(process_instructions(&mut context, &[init_instruction], &[])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+pub async fn test_mmr() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    let mut leaves = MmrLeaves::default();
+    let mut roots = Vec::new();
+    for i in 0..5u8 {
+        let append_instruction = make_append_mmr_leaf_instruction(
+            solana_program_mtree::ID,
+            context.payer.pubkey(),
+            context.payer.pubkey(),
+            vec![i],
+            i as u64,
+        )
+        .unwrap();
+        process_instructions(&mut context, &[append_instruction], &[])
+            .await
+            .unwrap();
+
+        leaves.push(hash_leaf([i]));
+        let mmr = get_info(&mut context).await.unwrap().mmr;
+        assert_eq!(mmr.leaf_count, i as u64 + 1);
+        assert_eq!(mmr.root, leaves.root());
+        roots.push(mmr.root);
+    }
+
+    // The appended leaves are stored in order in their page account.
+    let page = context
+        .banks_client
+        .get_account(find_mmr_page_pda(0, &solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(page.data.len(), MMR_PAGE_SIZE);
+    assert_eq!(page.data[..5 * 32].to_vec(), leaves.leaves().concat());
+
+    let proof = leaves.get_consistency_proof(3, 5).unwrap();
+    assert!(proof.verify(&roots[2], &roots[4]));
+    assert!(!proof.verify(&roots[1], &roots[4]));
+
+    // Proofs against an earlier root stay valid, the range is append-only.
+    let old_root = leaves.root_at(3).unwrap();
+    let proof = leaves.get_proof(1, 3).unwrap();
+    assert!(proof.verify(&old_root, hash_leaf([1])));
+
+    let root = get_info(&mut context).await.unwrap().mmr.root;
+    let proof = leaves.get_proof(4, 5).unwrap();
+    assert!(proof.verify(&root, hash_leaf([4])));
+
+    let wrong_page = make_append_mmr_leaf_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        vec![5],
+        MMR_PAGE_LEAVES,
+    )
+    .unwrap();
+    assert!(process_instructions(&mut context, &[wrong_page], &[])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+pub async fn test_mmr_leaf_rules() {
+    let (mut context, upgrade_authority) = start_with_upgrade_authority().await;
+
+    let config = TreeConfig {
+        leaf_mode: LeafMode::AuthorBound,
+        leaf_policy: LeafPolicy {
+            fixed_len: 4,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    init_tree(&mut context, &upgrade_authority, config)
+        .await
+        .unwrap();
+
+    let author = Keypair::new();
+    let too_short = make_append_mmr_leaf_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        author.pubkey(),
+        vec![1; 3],
+        0,
+    )
+    .unwrap();
+    assert!(process_instructions(&mut context, &[too_short], &[&author])
+        .await
+        .is_err());
+
+    let mut unsigned = make_append_mmr_leaf_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        author.pubkey(),
+        vec![1; 4],
+        0,
+    )
+    .unwrap();
+    unsigned.accounts[1].is_signer = false;
+    assert!(process_instructions(&mut context, &[unsigned], &[])
+        .await
+        .is_err());
+
+    let append_instruction = make_append_mmr_leaf_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        author.pubkey(),
+        vec![1; 4],
+        0,
+    )
+    .unwrap();
+    process_instructions(&mut context, &[append_instruction], &[&author])
+        .await
+        .unwrap();
+
+    let mut leaves = MmrLeaves::default();
+    leaves.push(hash_authored_leaf(&author.pubkey(), &[1; 4]));
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.mmr.root, leaves.root());
+    // The range does not touch the Merkle tree of the info.
+    assert_eq!(info.leaf_count, 0);
+}
+
+#[tokio::test]
+pub async fn test_verify_leaves() {
+    let mut context = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None)
+        .start_with_context()
+        .await;
+
+    for i in 0..16u8 {
+        let id = get_info(&mut context)
+            .await
+            .map(|info| info.tree_id)
+            .unwrap_or_default();
+        insert_leaf(&mut context, vec![i], id).await.unwrap();
+    }
+
+    let info = get_info(&mut context).await.unwrap();
+    let leaf_indexes = [0, 3, 4, 9, 15];
+    let mut proofs = Vec::new();
+    for leaf_index in leaf_indexes {
+        let (id, position) = info.leaf_position(leaf_index).unwrap();
+        let path = get_path_to_root(id);
+        let mut sub_trees = Vec::new();
+        for id in &path {
+            sub_trees.push(get_sub_tree(&mut context, *id).await.unwrap());
+        }
+        proofs.push(MerkleProof::from_sub_trees(&path, &sub_trees, position).unwrap());
+    }
+    let proof = MerkleMultiProof::from_proofs(&proofs).unwrap();
+    let leaves = leaf_indexes
+        .iter()
+        .map(|i| hash_leaf([*i as u8]))
+        .collect::<Vec<_>>();
+    assert!(proof.verify(&info.root_hash, &leaves));
+
+    let verify_instruction = make_verify_leaves_instruction(
+        solana_program_mtree::ID,
+        leaf_indexes.to_vec(),
+        leaves.clone(),
+        proof.clone(),
+    )
+    .unwrap();
+    process_instructions(&mut context, &[verify_instruction], &[])
+        .await
+        .unwrap();
+
+    let mut forged = leaves.clone();
+    forged[2] = hash_leaf([5]);
+    let verify_instruction = make_verify_leaves_instruction(
+        solana_program_mtree::ID,
+        leaf_indexes.to_vec(),
+        forged,
+        proof,
+    )
+    .unwrap();
+    assert!(
+        process_instructions(&mut context, &[verify_instruction], &[])
+            .await
+            .is_err()
+    );
+
+    // The parent of a leaf and its sibling verifies one level up, but not at
+    // the place of a leaf.
+    let node = MerkleProof {
+        position: proofs[0].position & 1,
+        siblings: proofs[0].siblings[..1].to_vec(),
+    }
+    .root(leaves[0]);
+    let node_proof = MerkleMultiProof::from_proofs(&[MerkleProof {
+        position: proofs[0].position >> 1,
+        siblings: proofs[0].siblings[1..].to_vec(),
+    }])
+    .unwrap();
+    assert!(node_proof.verify(&info.root_hash, &[node]));
+    let verify_instruction = make_verify_leaves_instruction(
+        solana_program_mtree::ID,
+        vec![leaf_indexes[0]],
+        vec![node],
+        node_proof,
+    )
+    .unwrap();
+    assert!(
+        process_instructions(&mut context, &[verify_instruction], &[])
+            .await
+            .is_err()
+    );
+}
+
+const CALLER_AUTHORITY_SEED: &[u8] = b"authority";
+
+/// Mock program that inserts its instruction data as a leaf, signing with its
+/// own PDA authority.
+fn process_caller_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts_iterator = &mut accounts.iter();
+    let mtree_program = next_account_info(accounts_iterator)?;
+    let payer = next_account_info(accounts_iterator)?;
+    let authority = next_account_info(accounts_iterator)?;
+    let info = next_account_info(accounts_iterator)?;
+    let system_program = next_account_info(accounts_iterator)?;
+    let sub_trees = accounts_iterator.as_slice();
+
+    let (_, bump) = Pubkey::find_program_address(&[CALLER_AUTHORITY_SEED], program_id);
+    let result = cpi::insert_leaf(
+        mtree_program,
+        InsertLeafAccounts {
+            payer,
+            author: authority,
+            info,
+            system_program,
+            sub_trees,
+        },
+        data.to_vec(),
+        &[&[CALLER_AUTHORITY_SEED, &[bump]]],
+    )?;
+
+    if result.leaf_hash != hash_leaf(data) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_insert_leaf_cpi_with_pda_authority() {
+    let caller_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None);
+    program_test.add_program(
+        "mtree_caller",
+        caller_id,
+        processor!(process_caller_instruction),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let (authority, _) = Pubkey::find_program_address(&[CALLER_AUTHORITY_SEED], &caller_id);
+    let mut expected_tree = SubTree::new();
+    for i in 0..2 {
+        let test_data = format!("audit_record_{}", i).as_bytes().to_vec();
+
+        let mut accounts = vec![AccountMeta::new_readonly(solana_program_mtree::ID, false)];
+        accounts.extend(
+            insert_leaf_account_metas(
+                &solana_program_mtree::ID,
+                context.payer.pubkey(),
+                authority,
+                0,
+            )
+            .into_iter()
+            .map(|mut meta| {
+                if meta.pubkey == authority {
+                    meta.is_signer = false;
+                }
+                meta
+            }),
+        );
+        let caller_instruction = Instruction {
+            program_id: caller_id,
+            accounts,
+            data: test_data.clone(),
+        };
+        let tx = Transaction::new_signed_with_payer(
+            &[caller_instruction],
+            Some(&context.payer.pubkey()),
+            &[&context.payer],
+            context.last_blockhash,
+        );
+        context.banks_client.process_transaction(tx).await.unwrap();
+        expected_tree.insert_leaf(hash_leaf(test_data));
+    }
+
+    let info = get_info(&mut context).await.unwrap();
+    assert_eq!(info.leaf_count, 2);
+    assert_eq!(info.root_hash, expected_tree.root_hash());
+}
+
+/// Starts the program with a program data account naming the returned
+/// keypair as its upgrade authority, as after an upgradeable deploy.
+async fn start_with_upgrade_authority() -> (ProgramTestContext, Keypair) {
+    let mut program_test = ProgramTest::new("solana_program_mtree", solana_program_mtree::ID, None);
+
+    let upgrade_authority = Keypair::new();
+    // `UpgradeableLoaderState::ProgramData` at slot 0.
+    let data = [
+        3u32.to_le_bytes().as_ref(),
+        &0u64.to_le_bytes(),
+        &[1],
+        upgrade_authority.pubkey().as_ref(),
+    ]
+    .concat();
+    program_test.add_account(
+        find_program_data_pda(&solana_program_mtree::ID).0,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: bpf_loader_upgradeable::ID,
+            ..Default::default()
+        },
+    );
+
+    (program_test.start_with_context().await, upgrade_authority)
+}
+
+async fn init_tree(
+    context: &mut ProgramTestContext,
+    upgrade_authority: &Keypair,
+    config: TreeConfig,
+) -> Result<(), BanksClientError> {
+    let init_instruction = make_init_tree_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        upgrade_authority.pubkey(),
+        config,
+    )
+    .unwrap();
+    process_instructions(context, &[init_instruction], &[upgrade_authority]).await
 }
 
 async fn insert_leaf(
@@ -131,9 +1479,14 @@ async fn insert_leaf(
     leaf: Vec<u8>,
     id: SubTreeId,
 ) -> Result<(), BanksClientError> {
-    let insert_instruction =
-        make_insert_leaf_instruction(solana_program_mtree::ID, context.payer.pubkey(), leaf, id)
-            .unwrap();
+    let insert_instruction = make_insert_leaf_instruction(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        leaf,
+        id,
+    )
+    .unwrap();
     let tx = Transaction::new_signed_with_payer(
         &[insert_instruction],
         Some(&context.payer.pubkey()),
@@ -143,6 +1496,100 @@ async fn insert_leaf(
     context.banks_client.process_transaction(tx).await
 }
 
+async fn insert_signed_leaf(
+    context: &mut ProgramTestContext,
+    signer: Pubkey,
+    signature: [u8; 64],
+    nonce: u64,
+    leaf: Vec<u8>,
+) -> Result<(), BanksClientError> {
+    let instructions = make_insert_signed_leaf_instructions(
+        solana_program_mtree::ID,
+        context.payer.pubkey(),
+        context.payer.pubkey(),
+        signer,
+        &signature,
+        nonce,
+        leaf,
+        0,
+    )
+    .unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+async fn process_instructions(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await
+}
+
+/// Reads all indexed leaves and checks them against the indexed tree root.
+async fn get_indexed_leaves(context: &mut ProgramTestContext) -> IndexedTreeLeaves {
+    let tree_acc = context
+        .banks_client
+        .get_account(find_indexed_tree_pda(&solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    let tree = IndexedTree::try_from_slice(&tree_acc.data).unwrap();
+
+    let mut leaves = Vec::new();
+    for index in 0..tree.next_index {
+        let leaf_acc = context
+            .banks_client
+            .get_account(find_indexed_leaf_pda(index, &solana_program_mtree::ID).0)
+            .await
+            .unwrap()
+            .unwrap();
+        leaves.push(IndexedLeaf::try_from_slice(&leaf_acc.data).unwrap());
+    }
+
+    let leaves = IndexedTreeLeaves::new(leaves);
+    assert_eq!(leaves.root(), tree.root);
+    leaves
+}
+
+async fn get_sparse_keys(context: &mut ProgramTestContext) -> Vec<SparseKey> {
+    let key_count = get_sparse_tree(context).await.key_count;
+    let mut keys = Vec::new();
+    for slot in 0..key_count {
+        let acc = context
+            .banks_client
+            .get_account(find_sparse_slot_pda(slot, &solana_program_mtree::ID).0)
+            .await
+            .unwrap()
+            .unwrap();
+        keys.push(SparseSlot::try_from_slice(&acc.data).unwrap().key);
+    }
+    keys
+}
+
+async fn get_sparse_tree(context: &mut ProgramTestContext) -> SparseTree {
+    let acc = context
+        .banks_client
+        .get_account(find_sparse_tree_pda(&solana_program_mtree::ID).0)
+        .await
+        .unwrap()
+        .unwrap();
+    SparseTree::try_from_slice(&acc.data).unwrap()
+}
+
 async fn get_info(context: &mut ProgramTestContext) -> Option<MTreeInfo> {
     let acc = context
         .banks_client